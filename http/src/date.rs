@@ -0,0 +1,162 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Abbreviated names of the week days, starting on Thursday like the Unix epoch.
+const WEEK_DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+/// Latest year of the dates that are parsed, the last with four digits.
+const MAX_YEAR: u64 = 9999;
+
+/// Abbreviated names of the months.
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Converts a number of days since the Unix epoch to a civil date (year, month, day).
+///
+/// # Arguments
+///
+/// * `days`: Number of days since 1 Jan 1970.
+fn civil_from_days(days: u64) -> (u64, usize, u64) {
+  // Shift the epoch to 1 Mar 0000, so leap days end the 400 years eras
+  let z = days + 719_468;
+  let era = z / 146_097;
+  let day_of_era = z % 146_097;
+  let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let month = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * month + 2) / 5 + 1;
+  let month = if month < 10 { month + 3 } else { month - 9 };
+  let year = year_of_era + era * 400 + u64::from(month <= 2);
+  (year, month as usize, day)
+}
+
+/// Gets the number of days in the given month.
+///
+/// # Arguments
+///
+/// * `year`: Year of the month.
+/// * `month`: Month, from 1 (January) to 12.
+fn days_in_month(year: u64, month: u64) -> u64 {
+  match month {
+    2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => 29,
+    2 => 28,
+    4 | 6 | 9 | 11 => 30,
+    _ => 31,
+  }
+}
+
+/// Converts a civil date to a number of days since the Unix epoch, the inverse of
+/// [`civil_from_days`].
+///
+/// # Arguments
+///
+/// * `year`: Year, from 1970 to 9999.
+/// * `month`: Month, from 1 (January) to 12.
+/// * `day`: Day of the month, from 1.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year / 400;
+  let year_of_era = year % 400;
+  let month = if month > 2 { month - 3 } else { month + 9 };
+  let day_of_year = (153 * month + 2) / 5 + day - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146_097 + day_of_era - 719_468
+}
+
+/// Parses an HTTP-date in its preferred format (e.g. `Sun, 06 Nov 1994 08:49:37 GMT`).
+///
+/// Returns `None` if the date is malformed, does not exist, or is before the Unix epoch
+/// or after the year 9999.
+///
+/// # Arguments
+///
+/// * `s`: Text of the date.
+pub fn parse(s: &str) -> Option<SystemTime> {
+  let (_, date) = s.trim().split_once(", ")?;
+  let parts: Vec<&str> = date.split(' ').collect();
+  let [day, month, year, time, "GMT"] = parts.as_slice() else {
+    return None;
+  };
+  let month = MONTHS.iter().position(|m| m == month)? as u64 + 1;
+  let (day, year): (u64, u64) = (day.parse().ok()?, year.parse().ok()?);
+  let clock: Vec<u64> = time.split(':').map(|n| n.parse().ok()).collect::<Option<_>>()?;
+  let [hours, minutes, seconds] = clock.as_slice() else {
+    return None;
+  };
+  if !(1970..=MAX_YEAR).contains(&year) || !(1..=days_in_month(year, month)).contains(&day) {
+    return None;
+  }
+  if *hours > 23 || *minutes > 59 || *seconds > 60 {
+    return None;
+  }
+
+  let secs = days_from_civil(year, month, day)
+    .checked_mul(86_400)?
+    .checked_add(hours * 3600 + minutes * 60 + seconds)?;
+  UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+/// Formats the given time as an HTTP-date (e.g. `Sun, 06 Nov 1994 08:49:37 GMT`).
+///
+/// Times before the Unix epoch are formatted as the epoch.
+///
+/// # Arguments
+///
+/// * `time`: Time to format.
+pub fn format(time: SystemTime) -> String {
+  let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+  let days = secs / 86_400;
+  let (year, month, day) = civil_from_days(days);
+  let secs_of_day = secs % 86_400;
+
+  format!(
+    "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+    WEEK_DAYS[(days % 7) as usize],
+    day,
+    MONTHS[month - 1],
+    year,
+    secs_of_day / 3600,
+    secs_of_day % 3600 / 60,
+    secs_of_day % 60
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_format() {
+    assert_eq!(format(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+    assert_eq!(format(UNIX_EPOCH + Duration::from_secs(784_111_777)), "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(format(UNIX_EPOCH + Duration::from_secs(951_782_400)), "Tue, 29 Feb 2000 00:00:00 GMT");
+  }
+
+  #[test]
+  fn test_parse() {
+    assert_eq!(parse("Sun, 06 Nov 1994 08:49:37 GMT"), Some(UNIX_EPOCH + Duration::from_secs(784_111_777)));
+    assert_eq!(parse("Tue, 29 Feb 2000 00:00:00 GMT"), Some(UNIX_EPOCH + Duration::from_secs(951_782_400)));
+    for invalid in ["", "yesterday", "Sun, 06 Nov 1994 08:49:37 UTC", "Sun, 06 Foo 1994 08:49:37 GMT", "Sun, 06 Nov 1994 25:00:00 GMT"] {
+      assert_eq!(parse(invalid), None, "{}", invalid);
+    }
+  }
+
+  #[test]
+  fn test_parse_impossible_dates() {
+    assert_eq!(parse("Fri, 31 Dec 9999 23:59:59 GMT"), Some(UNIX_EPOCH + Duration::from_secs(253_402_300_799)));
+    for invalid in [
+      "Sun, 06 Nov 300000000000 08:49:37 GMT",
+      "Sun, 06 Nov 18446744073709551615 08:49:37 GMT",
+      "Sat, 01 Jan 10000 00:00:00 GMT",
+      "Mon, 31 Feb 2020 00:00:00 GMT",
+      "Thu, 29 Feb 2001 00:00:00 GMT",
+      "Mon, 31 Apr 2024 00:00:00 GMT",
+    ] {
+      assert_eq!(parse(invalid), None, "{}", invalid);
+    }
+  }
+
+  #[test]
+  fn test_parse_formatted() {
+    let time = UNIX_EPOCH + Duration::from_secs(1_760_000_000);
+    assert_eq!(parse(&format(time)), Some(time));
+  }
+}
@@ -1,8 +1,20 @@
 use std::collections::HashMap;
-# [derive (Debug ,PartialEq)]
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+# [derive (Debug ,Clone ,Copy ,PartialEq)]
 pub enum  Method {
    GET,
+   HEAD,
    POST,
+   PUT,
+   DELETE,
+   PATCH,
+   OPTIONS,
+   TRACE,
+   CONNECT,
    UNINITIALIZED,
 
 
@@ -11,15 +23,36 @@ impl From <&str> for Method {
     fn from (value: &str ) -> Self {
         match value { 
             "GET" => Method::GET,
+            "HEAD" => Method::HEAD,
             "POST" => Method::POST,
+            "PUT" => Method::PUT,
+            "DELETE" => Method::DELETE,
+            "PATCH" => Method::PATCH,
+            "OPTIONS" => Method::OPTIONS,
+            "TRACE" => Method::TRACE,
+            "CONNECT" => Method::CONNECT,
             _ => Method::UNINITIALIZED,
             
         }
     }
 }
+impl Method {
+    /// Checks whether the method is safe, i.e. it is not meant to change server state.
+    pub fn is_safe(&self) -> bool {
+        matches!(self, Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE)
+    }
+
+    /// Checks whether repeating a request with the method has the same effect as sending it once.
+    pub fn is_idempotent(&self) -> bool {
+        self.is_safe() || matches!(self, Method::PUT | Method::DELETE)
+    }
+}
 #[derive(Debug , PartialEq)]
 
 pub enum Version {
+    /// HTTP/0.9, whose simple requests have no version token.
+    V0_9,
+    V1_0,
     V1_1,
     V2_0,
     UNINITIALIZED,
@@ -29,12 +62,36 @@ pub enum Version {
 impl From <&str> for Version {
     fn from (value: &str) -> Self {
         match value {
+            "HTTP/1.0" => Version::V1_0,
             "HTTP/1.1" => Version::V1_1,
             _ => Version::UNINITIALIZED,
         }
     }
  }
 
+/// Represents the reason a request could not be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// The header section is not valid UTF-8.
+    InvalidUtf8,
+    /// The request line is not made of a known method, a resource and a known version.
+    InvalidRequestLine(String),
+    /// A header line has no colon separating its name from its value.
+    InvalidHeader(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidUtf8 => f.write_str("the header section is not valid UTF-8"),
+            ParseError::InvalidRequestLine(line) => write!(f, "invalid request line {:?}", line),
+            ParseError::InvalidHeader(line) => write!(f, "invalid header line {:?}", line),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 #[derive(Debug,PartialEq)]
 
 pub enum Resource {
@@ -47,21 +104,59 @@ pub struct  HttpRequest{
    pub version: Version,
    pub resource: Resource,
    pub headers: HashMap<String,String>,
-   pub msg_body : String,
+   /// Raw body of the request, which may be binary.
+   pub msg_body : Vec<u8>,
+   /// Original text the request was parsed from, only kept by [`HttpRequest::with_raw`]
+   /// to help debugging malformed requests.
+   pub raw: Option<String>,
+   /// Address of the peer the request was received from, set by the server.
+   pub peer_addr: Option<SocketAddr>,
+   /// Whether the `X-Forwarded-*` headers are trusted, i.e. set by a proxy in front of
+   /// the server rather than by the client.
+   pub trust_proxy: bool,
+   /// Temporary file holding a body too large to be kept in memory, set by the server,
+   /// in which case [`HttpRequest::msg_body`] is empty.
+   pub body_file: Option<PathBuf>,
 }
 
 impl From<String> for HttpRequest {
 
     fn from(req: String) -> Self {
+        req.as_str().into()
+    }
+}
+
+impl From<&str> for HttpRequest {
+
+    fn from(req: &str) -> Self {
+        req.as_bytes().into()
+    }
+}
+
+        /// Splits a request at the first blank line, into its header section and its body.
+        fn split_head(req: &[u8]) -> (&[u8], &[u8]) {
+            let find = |separator: &[u8]| req.windows(separator.len()).position(|w| w == separator).map(|i| (i, separator.len()));
+            match find(b"\r\n\r\n").or_else(|| find(b"\n\n")) {
+                Some((i, length)) => (&req[..i], &req[i + length..]),
+                None => (req, &[]),
+            }
+        }
+
+impl From<&[u8]> for HttpRequest {
+
+    fn from(req: &[u8]) -> Self {
         let mut parsed_method = Method::UNINITIALIZED;
         let mut parsed_version = Version::V1_1;
         let mut parsed_resource = Resource::Path( "".to_string());
         let mut parsed_headers = HashMap::new();
-        let mut parsed_msg_body = "";
 
-        for line in req.lines(){
-           // process_req_line()
-            if line.contains("HTTP") {
+        // The header section ends at the first blank line, everything after it is the body
+        let (head, parsed_msg_body) = split_head(req);
+        let head = String::from_utf8_lossy(head);
+
+        for (index, line) in head.lines().enumerate() {
+           // process_req_line(), the request line being the first one
+            if index == 0 {
                 let (method ,resource,version) =process_req_line(line);
                 parsed_method =method;
                 parsed_version=version;
@@ -71,10 +166,6 @@ impl From<String> for HttpRequest {
                 let (key ,value) = process_header_line(line);
                 parsed_headers.insert(key, value);
                 
-            }else if line.len ()== 0 {
-                
-            }else {
-                parsed_msg_body =line;
             }
         }
 
@@ -83,41 +174,281 @@ impl From<String> for HttpRequest {
             version:parsed_version,
             resource:parsed_resource,
             headers:parsed_headers,
-            msg_body:parsed_msg_body.to_string(),
+            msg_body:parsed_msg_body.to_vec(),
+            raw: None,
+            peer_addr: None,
+            trust_proxy: false,
+            body_file: None,
         }
     }
         }
         fn process_req_line(s: &str) ->(Method,Resource,Version) {
-            let mut words = s.split_whitespace();
-            let method =words.next().unwrap();
-            let resource = words.next().unwrap();
-            let version =words.next().unwrap();
-            (
-                method.into(),
-                Resource::Path(resource.to_string()),
-                version.into(),
-            )
+            let words: Vec<&str> = s.split_whitespace().collect();
+            match words.as_slice() {
+                [method, resource, version] => (
+                    (*method).into(),
+                    Resource::Path(resource.to_string()),
+                    (*version).into(),
+                ),
+                // A simple request of HTTP/0.9 only names the resource to get
+                ["GET", resource] if resource.starts_with('/') => (Method::GET, Resource::Path(resource.to_string()), Version::V0_9),
+                // Anything else is not a request line
+                _ => (Method::UNINITIALIZED, Resource::Path("".to_string()), Version::UNINITIALIZED),
+            }
           }
         
 
           fn process_header_line (s: &str) ->(String,String) {
-            let mut  header_items = s.split(":");
-            let mut key =String::from ("");
-            let mut value = "".to_string();
-            if let Some(k) = header_items.next(){
-                key =k.to_string();
-            } 
-               if let Some(v) = header_items.next (){
-                value = v.to_string()
-               }
-               (key ,value )
-
+            // Split on the first colon only, so values such as "localhost:3000" stay intact
+            match s.split_once(':') {
+                Some((key, value)) => (key.trim().to_string(), value.trim().to_string()),
+                None => (s.trim().to_string(), "".to_string()),
+            }
           }
 
+impl HttpRequest {
+    /// Parses a request, rejecting it unless its header section is well-formed.
+    ///
+    /// Unlike the lenient conversions from text and bytes, which leave the unparsable parts
+    /// uninitialized, it fails on the first malformed line.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`: Raw bytes of the request.
+    pub fn parse(req: &[u8]) -> Result<Self, ParseError> {
+        let (head, _) = split_head(req);
+        let head = std::str::from_utf8(head).map_err(|_| ParseError::InvalidUtf8)?;
+        let mut lines = head.lines();
+
+        let request_line = lines.next().unwrap_or("");
+        if let (Method::UNINITIALIZED, _, _) | (_, _, Version::UNINITIALIZED) = process_req_line(request_line) {
+            return Err(ParseError::InvalidRequestLine(request_line.to_string()));
+        }
+        if let Some(line) = lines.find(|line| !line.contains(':')) {
+            return Err(ParseError::InvalidHeader(line.to_string()));
+        }
+
+        Ok(req.into())
+    }
+
+    /// Creates a new HTTP/1.1 [`HttpRequest`] object without headers nor body, to build a
+    /// request directly rather than parse it, e.g. in the unit tests of handlers.
+    ///
+    /// # Arguments
+    ///
+    /// * `method`: HTTP method of the request.
+    /// * `resource`: Requested path, with its query if any.
+    pub fn new(method: Method, resource: &str) -> Self {
+        HttpRequest {
+            method,
+            version: Version::V1_1,
+            resource: Resource::Path(resource.to_string()),
+            headers: HashMap::new(),
+            msg_body: Vec::new(),
+            raw: None,
+            peer_addr: None,
+            trust_proxy: false,
+            body_file: None,
+        }
+    }
+
+    /// Sets the given header of the request, replacing any previous value.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: Name of the header.
+    /// * `value`: Value of the header.
+    pub fn with_header(mut self, key: &str, value: &str) -> Self {
+        self.headers.retain(|k, _| !k.eq_ignore_ascii_case(key));
+        self.headers.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Sets the body of the request.
+    ///
+    /// # Arguments
+    ///
+    /// * `body`: Raw contents of the body.
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.msg_body = body.into();
+        self
+    }
+
+    /// Parses a request, keeping a copy of its original text in [`HttpRequest::raw`].
+    ///
+    /// # Arguments
+    ///
+    /// * `req`: Raw bytes of the request.
+    pub fn with_raw(req: &[u8]) -> Self {
+        let mut request: HttpRequest = req.into();
+        request.raw = Some(String::from_utf8_lossy(req).into_owned());
+        request
+    }
+
+    /// Gets the raw body of the request.
+    pub fn body_bytes(&self) -> &[u8] {
+        &self.msg_body
+    }
+
+    /// Gets a reader over the body of the request, whether it is kept in memory or in a
+    /// temporary file.
+    pub fn body_reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        match &self.body_file {
+            Some(path) => Ok(Box::new(File::open(path)?)),
+            None => Ok(Box::new(self.msg_body.as_slice())),
+        }
+    }
+
+    /// Gets the body of the request as text, or `None` if it is not valid UTF-8.
+    pub fn body_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.msg_body).ok()
+    }
+
+    /// Gets the value of the given header, matching its name case-insensitively.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: Name of the HTTP header.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Gets the elements of the comma-separated list held by the given header (e.g.
+    /// `Accept-Encoding`), trimmed, leaving the commas of quoted strings intact.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: Name of the HTTP header.
+    pub fn header_values(&self, name: &str) -> Vec<&str> {
+        let Some(value) = self.header(name) else {
+            return Vec::new();
+        };
+        let mut values = Vec::new();
+        let (mut start, mut quoted, mut escaped) = (0, false, false);
+        for (i, c) in value.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quoted => escaped = true,
+                '"' => quoted = !quoted,
+                ',' if !quoted => {
+                    values.push(value[start..i].trim());
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        values.push(value[start..].trim());
+        values.retain(|v| !v.is_empty());
+        values
+    }
+
+    /// Gets the charset the body is declared in by the `charset` parameter of the
+    /// `Content-Type` header, `utf-8` if it declares none.
+    pub fn content_charset(&self) -> Option<&str> {
+        let charset = self.header("Content-Type").and_then(|content_type| {
+            content_type
+                .split(';')
+                .skip(1)
+                .filter_map(|param| param.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("charset"))
+                .map(|(_, value)| value.trim().trim_matches('"'))
+        });
+        Some(charset.unwrap_or("utf-8"))
+    }
+
+    /// Gets the value of the given cookie sent in the `Cookie` header, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: Name of the cookie.
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.header("Cookie")?
+            .split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.trim_matches('"'))
+    }
+
+    /// Checks whether the request was sent by a script, as announced by the
+    /// `X-Requested-With: XMLHttpRequest` header, e.g. to answer it in JSON.
+    pub fn is_ajax(&self) -> bool {
+        self.header("X-Requested-With").is_some_and(|value| value.trim().eq_ignore_ascii_case("XMLHttpRequest"))
+    }
+
+    /// Gets the value of the `Host` header, if any.
+    pub fn host(&self) -> Option<&str> {
+        self.header("Host")
+    }
+
+    /// Gets the scheme the request was received with, `http` as the server only listens
+    /// to plain TCP connections, unless a trusted proxy forwarded it with `X-Forwarded-Proto`.
+    pub fn scheme(&self) -> &str {
+        match self.forwarded("X-Forwarded-Proto") {
+            Some(proto) if proto.eq_ignore_ascii_case("https") => "https",
+            _ => "http",
+        }
+    }
+
+    /// Gets the address of the client, taken from `X-Forwarded-For` when the proxy is
+    /// trusted and from the peer of the connection otherwise.
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        match self.forwarded("X-Forwarded-For").and_then(|ip| ip.parse().ok()) {
+            Some(ip) => Some(ip),
+            None => self.peer_addr.map(|addr| addr.ip()),
+        }
+    }
+
+    /// Gets the first value of the given forwarded header, i.e. the one closest to the
+    /// client, if the proxy is trusted.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: Name of the `X-Forwarded-*` header.
+    fn forwarded(&self, name: &str) -> Option<&str> {
+        if !self.trust_proxy {
+            return None;
+        }
+        self.header(name).and_then(|value| value.split(',').next()).map(str::trim)
+    }
+
+    /// Checks whether the client wants the connection kept open after this request.
+    ///
+    /// HTTP/1.1 connections persist unless the client sends `Connection: close`, whereas
+    /// HTTP/1.0 connections close unless the client sends `Connection: keep-alive`.
+    pub fn wants_keep_alive(&self) -> bool {
+        let has_option = |option: &str| {
+            self.header("Connection")
+                .is_some_and(|value| value.split(',').any(|o| o.trim().eq_ignore_ascii_case(option)))
+        };
+
+        match self.version {
+            Version::V1_0 => has_option("keep-alive"),
+            _ => !has_option("close"),
+        }
+    }
+
+    /// Gets the path of the requested resource, without its query string.
+    pub fn path(&self) -> &str {
+        let Resource::Path(resource) = &self.resource;
+        resource.split_once('?').map_or(resource.as_str(), |(path, _)| path)
+    }
+
+    /// Gets the raw query string of the requested resource, i.e. everything after the
+    /// first `?`, if any.
+    pub fn query_string(&self) -> Option<&str> {
+        let Resource::Path(resource) = &self.resource;
+        resource.split_once('?').map(|(_, query)| query)
+    }
+}
+
 
 # [cfg(test)]
 mod  tests {
     use super::*;
+    use proptest::prelude::*;
     #[test]
     fn test_method_into (){
 
@@ -125,9 +456,259 @@ mod  tests {
         assert_eq!(m,Method::GET);
     }
     #[test]
+    fn test_method_into_extended (){
+        let m: Method = "TRACE".into();
+        assert_eq!(m, Method::TRACE);
+        let m: Method = "CONNECT".into();
+        assert_eq!(m, Method::CONNECT);
+        let m: Method = "BREW".into();
+        assert_eq!(m, Method::UNINITIALIZED);
+    }
+    #[test]
+    fn test_method_classification() {
+        let cases = [
+            (Method::GET, true, true),
+            (Method::HEAD, true, true),
+            (Method::OPTIONS, true, true),
+            (Method::TRACE, true, true),
+            (Method::PUT, false, true),
+            (Method::DELETE, false, true),
+            (Method::POST, false, false),
+            (Method::PATCH, false, false),
+            (Method::CONNECT, false, false),
+            (Method::UNINITIALIZED, false, false),
+        ];
+        for (method, safe, idempotent) in cases {
+            assert_eq!(method.is_safe(), safe, "{:?}", method);
+            assert_eq!(method.is_idempotent(), idempotent, "{:?}", method);
+        }
+    }
+    #[test]
     fn test_version_into (){
         let m: Version = "HTTP/1.1".into();
         assert_eq!(m, Version::V1_1);
+        let m: Version = "HTTP/1.0".into();
+        assert_eq!(m, Version::V1_0);
+    }
+    #[test]
+    fn test_request_line_without_version() {
+        let req: HttpRequest = String::from("GET /path\r\n\r\n").into();
+        assert_eq!(req.method, Method::GET);
+        assert_eq!(req.version, Version::V0_9);
+        assert_eq!(req.resource, Resource::Path("/path".to_string()));
+    }
+    #[test]
+    fn test_malformed_request_lines() {
+        for raw in ["\r\n\r\n", "GET\r\n\r\n", "POST /path\r\n\r\n", "GET HTTP/1.1\r\n\r\n", "GET / HTTP/1.1 extra\r\n\r\n"] {
+            let req: HttpRequest = raw.into();
+            assert_eq!(req.method, Method::UNINITIALIZED, "{:?}", raw);
+        }
+    }
+    #[test]
+    fn test_header_mentioning_http() {
+        let req: HttpRequest = String::from("GET / HTTP/1.1\r\nUpgrade: HTTP/2.0\r\n\r\n").into();
+        assert_eq!(req.resource, Resource::Path("/".to_string()));
+        assert_eq!(req.header("Upgrade"), Some("HTTP/2.0"));
+    }
+    #[test]
+    fn test_body_with_colons() {
+        let req: HttpRequest = String::from("POST /api HTTP/1.1\r\nHost: localhost\r\n\r\n{\"order_id\": 3}").into();
+        assert_eq!(req.body_str(), Some("{\"order_id\": 3}"));
+        assert_eq!(req.headers.len(), 1);
+    }
+    #[test]
+    fn test_host_present() {
+        let req: HttpRequest = String::from("GET / HTTP/1.1\r\nHost: localhost:3000\r\n\r\n").into();
+        assert_eq!(req.host(), Some("localhost:3000"));
+    }
+    #[test]
+    fn test_host_absent() {
+        let req: HttpRequest = String::from("GET / HTTP/1.1\r\nAccept: */*\r\n\r\n").into();
+        assert_eq!(req.host(), None);
+    }
+    #[test]
+    fn test_host_case_insensitive() {
+        let req: HttpRequest = String::from("GET / HTTP/1.1\r\nhOST: example.com\r\n\r\n").into();
+        assert_eq!(req.host(), Some("example.com"));
+    }
+    /// Valid request the edited inputs start from.
+    const VALID_REQUEST: &[u8] = b"POST /api/shipping/orders?id=1 HTTP/1.1\r\nHost: localhost:3000\r\nContent-Length: 2\r\n\r\n{}";
+
+    /// Generates bytes, favouring the ones meaningful to the parser.
+    fn request_byte() -> impl Strategy<Value = u8> {
+        prop_oneof![
+            1 => any::<u8>(),
+            3 => prop::sample::select(b"\r\n: /?GETPOSHTP1.0\xff\x00".to_vec()),
+        ]
+    }
+
+    /// Generates inputs: either arbitrary bytes or the valid request with random edits.
+    fn request_input() -> impl Strategy<Value = Vec<u8>> {
+        let edits = prop::collection::vec((any::<prop::sample::Index>(), 0..3u8, request_byte()), 0..16);
+        prop_oneof![
+            prop::collection::vec(request_byte(), 0..256),
+            edits.prop_map(|edits| {
+                let mut input = VALID_REQUEST.to_vec();
+                for (at, edit, byte) in edits {
+                    let at = at.index(input.len() + 1);
+                    match edit {
+                        0 if at < input.len() => { input.remove(at); }
+                        1 if at < input.len() => input[at] = byte,
+                        _ => input.insert(at, byte),
+                    }
+                }
+                input
+            }),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(2048))]
+
+        #[test]
+        fn test_arbitrary_requests_never_panic(input in request_input()) {
+            let lenient: HttpRequest = input.as_slice().into();
+            match HttpRequest::parse(&input) {
+                Ok(req) => {
+                    prop_assert_ne!(&req.method, &Method::UNINITIALIZED);
+                    prop_assert_ne!(&req.version, &Version::UNINITIALIZED);
+                    prop_assert_eq!(req, lenient);
+                }
+                Err(ParseError::InvalidUtf8) => prop_assert!(std::str::from_utf8(split_head(&input).0).is_err()),
+                Err(ParseError::InvalidRequestLine(_)) | Err(ParseError::InvalidHeader(_)) => {}
+            }
+        }
+    }
+    #[test]
+    fn test_malformed_requests_parse_error() {
+        let cases: [(&[u8], ParseError); 5] = [
+            (b"GET /caf\xe9 HTTP/1.1\r\n\r\n", ParseError::InvalidUtf8),
+            (b"", ParseError::InvalidRequestLine("".to_string())),
+            (b"BREW /pot HTTP/1.1\r\n\r\n", ParseError::InvalidRequestLine("BREW /pot HTTP/1.1".to_string())),
+            (b"GET / HTTP/3.5\r\n\r\n", ParseError::InvalidRequestLine("GET / HTTP/3.5".to_string())),
+            (b"GET / HTTP/1.1\r\nHost localhost\r\n\r\n", ParseError::InvalidHeader("Host localhost".to_string())),
+        ];
+        for (raw, error) in cases {
+            assert_eq!(HttpRequest::parse(raw), Err(error));
+        }
+        assert!(HttpRequest::parse(b"GET /beach\xff\xfe HTTP/1.1\r\nHost: localhost\r\n\r\n").is_err());
+        assert!(HttpRequest::parse(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n\xff\xfe").is_ok());
+    }
+    #[test]
+    fn test_wants_keep_alive() {
+        let cases = [
+            ("HTTP/1.1", "", true),
+            ("HTTP/1.1", "Connection: close\r\n", false),
+            ("HTTP/1.1", "Connection: keep-alive\r\n", true),
+            ("HTTP/1.0", "", false),
+            ("HTTP/1.0", "Connection: Keep-Alive\r\n", true),
+            ("HTTP/1.0", "Connection: close\r\n", false),
+            ("HTTP/1.1", "Connection: Upgrade, close\r\n", false),
+        ];
+        for (version, header, expected) in cases {
+            let req: HttpRequest = format!("GET / {}\r\n{}\r\n", version, header).into();
+            assert_eq!(req.wants_keep_alive(), expected, "{} {:?}", version, header);
+        }
+    }
+    #[test]
+    fn test_binary_body() {
+        let mut raw = b"POST /upload HTTP/1.1\r\nContent-Type: application/octet-stream\r\n\r\n".to_vec();
+        let body: Vec<u8> = (0..=255).collect();
+        raw.extend_from_slice(&body);
+
+        let req: HttpRequest = raw.as_slice().into();
+        assert_eq!(req.header("Content-Type"), Some("application/octet-stream"));
+        assert_eq!(req.body_bytes(), body.as_slice());
+        assert_eq!(req.body_str(), None);
+    }
+    #[test]
+    fn test_header_values() {
+        let req: HttpRequest = "GET / HTTP/1.1\r\nAccept-Encoding: gzip, deflate,br\r\n\
+                                If-None-Match: \"a,b\", \"c\\\",d\",, W/\"e\"\r\n\r\n".into();
+        assert_eq!(req.header_values("accept-encoding"), vec!["gzip", "deflate", "br"]);
+        assert_eq!(req.header_values("If-None-Match"), vec!["\"a,b\"", "\"c\\\",d\"", "W/\"e\""]);
+        assert!(req.header_values("Cache-Control").is_empty());
+    }
+    #[test]
+    fn test_content_charset() {
+        let req: HttpRequest = "POST / HTTP/1.1\r\nContent-Type: text/plain; charset=iso-8859-1\r\n\r\n".into();
+        assert_eq!(req.content_charset(), Some("iso-8859-1"));
+
+        let req: HttpRequest = "POST / HTTP/1.1\r\ncontent-type: text/html;Charset=\"Windows-1252\"\r\n\r\n".into();
+        assert_eq!(req.content_charset(), Some("Windows-1252"));
+
+        let req: HttpRequest = "POST / HTTP/1.1\r\nContent-Type: text/plain\r\n\r\n".into();
+        assert_eq!(req.content_charset(), Some("utf-8"));
+    }
+    #[test]
+    fn test_is_ajax() {
+        let req: HttpRequest = "GET / HTTP/1.1\r\nx-requested-with: xmlhttprequest\r\n\r\n".into();
+        assert!(req.is_ajax());
+
+        let req: HttpRequest = "GET / HTTP/1.1\r\nX-Requested-With: Fetch\r\n\r\n".into();
+        assert!(!req.is_ajax());
+
+        let req: HttpRequest = "GET / HTTP/1.1\r\n\r\n".into();
+        assert!(!req.is_ajax());
+    }
+    #[test]
+    fn test_cookie() {
+        let req: HttpRequest = "GET / HTTP/1.1\r\nCookie: theme=dark; session_id=\"a1b2\";empty=\r\n\r\n".into();
+        assert_eq!(req.cookie("theme"), Some("dark"));
+        assert_eq!(req.cookie("session_id"), Some("a1b2"));
+        assert_eq!(req.cookie("empty"), Some(""));
+        assert_eq!(req.cookie("Theme"), None);
+
+        let req: HttpRequest = "GET / HTTP/1.1\r\n\r\n".into();
+        assert_eq!(req.cookie("theme"), None);
+    }
+    #[test]
+    fn test_forwarded_headers_ignored_by_default() {
+        let mut req: HttpRequest =
+            "GET / HTTP/1.1\r\nX-Forwarded-For: 203.0.113.7\r\nX-Forwarded-Proto: https\r\n\r\n".into();
+        req.peer_addr = Some("10.0.0.2:50000".parse().unwrap());
+
+        assert_eq!(req.client_ip(), Some("10.0.0.2".parse().unwrap()));
+        assert_eq!(req.scheme(), "http");
+    }
+    #[test]
+    fn test_forwarded_headers_honored_behind_trusted_proxy() {
+        let mut req: HttpRequest =
+            "GET / HTTP/1.1\r\nX-Forwarded-For: 203.0.113.7, 10.0.0.1\r\nX-Forwarded-Proto: https\r\n\r\n".into();
+        req.peer_addr = Some("10.0.0.2:50000".parse().unwrap());
+        req.trust_proxy = true;
+
+        assert_eq!(req.client_ip(), Some("203.0.113.7".parse().unwrap()));
+        assert_eq!(req.scheme(), "https");
+
+        let mut req: HttpRequest = "GET / HTTP/1.1\r\nX-Forwarded-For: unknown\r\n\r\n".into();
+        req.peer_addr = Some("10.0.0.2:50000".parse().unwrap());
+        req.trust_proxy = true;
+        assert_eq!(req.client_ip(), Some("10.0.0.2".parse().unwrap()));
+    }
+    #[test]
+    fn test_path_and_query_string() {
+        let req: HttpRequest = "GET /api/shipping/orders?status=Shipped&page=2 HTTP/1.1\r\n\r\n".into();
+        assert_eq!(req.path(), "/api/shipping/orders");
+        assert_eq!(req.query_string(), Some("status=Shipped&page=2"));
+
+        let req: HttpRequest = "GET /api/shipping/orders HTTP/1.1\r\n\r\n".into();
+        assert_eq!(req.path(), "/api/shipping/orders");
+        assert_eq!(req.query_string(), None);
+
+        let req: HttpRequest = "GET /search? HTTP/1.1\r\n\r\n".into();
+        assert_eq!(req.path(), "/search");
+        assert_eq!(req.query_string(), Some(""));
+    }
+    #[test]
+    fn test_raw_request_kept_when_enabled() {
+        let s = "POST /api/shipping/orders HTTP/1.1\r\nHost: localhost\r\n\r\n{}";
+        let req = HttpRequest::with_raw(s.as_bytes());
+        assert_eq!(req.raw.as_deref(), Some(s));
+        assert_eq!(req.body_bytes(), b"{}");
+
+        let req: HttpRequest = s.into();
+        assert_eq!(req.raw, None);
     }
 }
    #[test]
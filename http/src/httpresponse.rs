@@ -1,45 +1,145 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io::{Result, Write};
+use std::fmt;
+use std::io::{Read, Result, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, SystemTimeError};
+
+use log::warn;
+use serde::Serialize;
+
+use crate::{date, httprequest::HttpRequest, url};
+
+/// HTTP status numerical codes supported by responses, along with their status text.
+const STATUSES: [(&str, &str); 28] = [
+  ("200", "OK"),
+  ("201", "Created"),
+  ("202", "Accepted"),
+  ("204", "No Content"),
+  ("206", "Partial Content"),
+  ("301", "Moved Permanently"),
+  ("302", "Found"),
+  ("304", "Not Modified"),
+  ("308", "Permanent Redirect"),
+  ("400", "Bad Request"),
+  ("401", "Unauthorized"),
+  ("403", "Forbidden"),
+  ("404", "Not Found"),
+  ("405", "Method Not Allowed"),
+  ("408", "Request Timeout"),
+  ("409", "Conflict"),
+  ("412", "Precondition Failed"),
+  ("413", "Payload Too Large"),
+  ("414", "URI Too Long"),
+  ("416", "Range Not Satisfiable"),
+  ("417", "Expectation Failed"),
+  ("429", "Too Many Requests"),
+  ("500", "Internal Server Error"),
+  ("501", "Not Implemented"),
+  ("502", "Bad Gateway"),
+  ("503", "Service Unavailable"),
+  ("504", "Gateway Timeout"),
+  ("505", "HTTP Version Not Supported"),
+];
+
+/// Gets the status text of the given HTTP status numerical code, the generic one of its
+/// class for a code without a known status text, e.g. `Client Error` for `499`.
+///
+/// # Arguments
+///
+/// * `code`: HTTP status numerical code.
+fn status_text_of(code: &str) -> &'static str {
+  let known = STATUSES.iter().find(|(c, _)| *c == code);
+  known.map_or_else(
+    || match code.as_bytes().first() {
+      Some(b'1') => "Informational",
+      Some(b'2') => "Success",
+      Some(b'3') => "Redirection",
+      Some(b'4') => "Client Error",
+      _ => "Server Error",
+    },
+    |(_, text)| text,
+  )
+}
+
+/// Size of the chunks a streamed body is read and sent in.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Represents a body of unknown length, read from a source as it is sent.
+///
+/// Clones share the source, so only the first one sent gets its contents.
+#[derive(Clone)]
+pub struct BodyReader(Arc<Mutex<Box<dyn Read + Send>>>);
+
+impl fmt::Debug for BodyReader {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("BodyReader")
+  }
+}
+
+impl PartialEq for BodyReader {
+  fn eq(&self, other: &Self) -> bool {
+    Arc::ptr_eq(&self.0, &other.0)
+  }
+}
 
 /// Represents an HTTP response to a request.
-#[derive(Debug, PartialEq, Clone)]
+///
+/// Responses are equal when they have the same headers, whatever the order they were set
+/// in, although they are serialized in that order.
+#[derive(Debug, Clone)]
 pub struct HttpResponse<'a> {
   /// HTTP protocol version.
   version: &'a str,
   /// HTTP status numerical code.
-  status_code: &'a str,
+  status_code: Cow<'a, str>,
   // HTTP status text.
   status_text: &'a str,
-  /// Headers of the HTTP response.
-  headers: Option<HashMap<&'a str, &'a str>>,
+  /// Headers of the HTTP response, in the order they are sent.
+  headers: Vec<(String, String)>,
   /// Body of the HTTP response.
-  body: Option<String>,
+  body: Option<Vec<u8>>,
+  /// Source of the body of the HTTP response, when streamed instead.
+  reader: Option<BodyReader>,
+  /// Whether only the status line and headers are sent, as the answer to a `HEAD` request.
+  head_only: bool,
 }
 
 impl<'a> Default for HttpResponse<'a> {
   fn default() -> Self {
     Self {
       version: "HTTP/1.1",
-      status_code: "200",
+      status_code: Cow::Borrowed("200"),
       status_text: "OK",
-      headers: None,
+      headers: Vec::new(),
       body: None,
+      reader: None,
+      head_only: false,
     }
   }
 }
 
+impl<'a> PartialEq for HttpResponse<'a> {
+  fn eq(&self, other: &Self) -> bool {
+    let sorted = |headers: &[(String, String)]| {
+      let mut headers = headers.to_vec();
+      headers.sort();
+      headers
+    };
+
+    self.version == other.version
+      && self.status_code == other.status_code
+      && self.status_text == other.status_text
+      && self.body == other.body
+      && self.reader == other.reader
+      && self.head_only == other.head_only
+      && sorted(&self.headers) == sorted(&other.headers)
+  }
+}
+
 impl<'a> From<HttpResponse<'a>> for String {
   fn from(value: HttpResponse<'a>) -> String {
-    let res = value.clone();
-    format!(
-      "{} {} {}\r\n{}Content-Length: {}\r\n\r\n{}",
-      &res.version(),
-      &res.status_code(),
-      &res.status_text(),
-      &res.headers(),
-      if res.body.is_some() { res.body().len() } else { 0 },
-      &res.body()
-    )
+    String::from_utf8_lossy(&value.to_bytes()).into_owned()
   }
 }
 
@@ -55,34 +155,299 @@ impl<'a> HttpResponse<'a> {
     status_code: &'a str,
     headers: Option<HashMap<&'a str, &'a str>>,
     body: Option<String>,
+  ) -> HttpResponse<'a> {
+    Self::with_bytes(status_code, headers, body.map(String::into_bytes))
+  } // end fn new()
+
+  /// Creates an new [`HttpResponse`] object with a binary body.
+  ///
+  /// # Arguments
+  ///
+  /// * `status_code`: HTTP status numerical code for the response.
+  /// * `headers`: Set of HTTP headers for the response.
+  /// * `body`: Raw contents of the HTTP body for the response.
+  pub fn with_bytes(
+    status_code: &'a str,
+    headers: Option<HashMap<&'a str, &'a str>>,
+    body: Option<Vec<u8>>,
   ) -> HttpResponse<'a> {
     let mut response: HttpResponse<'a> = HttpResponse::default();
 
     if status_code != "200" {
-      response.status_code = status_code.into();
+      response.status_code = Cow::Borrowed(status_code);
     }
 
-    response.headers = match &headers {
-      Some(_h) => headers,
-      None => {
-        let mut h: HashMap<&str, &str> = HashMap::new();
-        h.insert("Content-Type", "text/html");
-        Some(h)
+    response.headers = match headers {
+      Some(h) => {
+        // Sort the given headers so the serialized response is deterministic
+        let mut pairs: Vec<(String, String)> =
+          h.into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        pairs.sort();
+        pairs
       }
+      None => vec![("Content-Type".to_string(), "text/html".to_string())],
     };
 
-    response.status_text = match response.status_code {
-      "200" => "OK",
-      "400" => "Bad Request",
-      "404" => "Not Found",
-      "500" => "Internal Server Error",
-      _ => "Not Found",
-    };
+    response.status_text = status_text_of(&response.status_code);
 
     response.body = body;
 
     response
-  } // end fn new()
+  } // end fn with_bytes()
+
+  /// Creates a new [`HttpResponse`] object with status `200 OK` and the given value
+  /// serialized as its JSON body.
+  ///
+  /// Returns the error of the serialization if the value cannot be serialized, e.g. a
+  /// map whose keys are not strings.
+  ///
+  /// # Arguments
+  ///
+  /// * `value`: Value to serialize.
+  pub fn from_json<T: Serialize + ?Sized>(value: &T) -> std::result::Result<HttpResponse<'a>, serde_json::Error> {
+    Self::json(serde_json::to_vec(value)?)
+  }
+
+  /// Creates a new [`HttpResponse`] object with status `200 OK` and the given value
+  /// serialized as its JSON body, indented to be read by people.
+  ///
+  /// # Arguments
+  ///
+  /// * `value`: Value to serialize.
+  pub fn from_json_pretty<T: Serialize + ?Sized>(value: &T) -> std::result::Result<HttpResponse<'a>, serde_json::Error> {
+    Self::json(serde_json::to_vec_pretty(value)?)
+  }
+
+  /// Creates a new [`HttpResponse`] object with status `200 OK` and the given JSON body.
+  ///
+  /// # Arguments
+  ///
+  /// * `body`: Serialized JSON body.
+  fn json(body: Vec<u8>) -> std::result::Result<HttpResponse<'a>, serde_json::Error> {
+    let mut response = Self::with_bytes("200", Some(HashMap::new()), Some(body));
+    response.set_header("Content-Type", "application/json;charset=UTF-8");
+    Ok(response)
+  }
+
+  /// Creates a new [`HttpResponse`] object whose body is read from the given source as
+  /// it is sent, with chunked transfer encoding since its length is unknown.
+  ///
+  /// # Arguments
+  ///
+  /// * `status_code`: HTTP status numerical code for the response.
+  /// * `headers`: Set of HTTP headers for the response.
+  /// * `reader`: Source of the HTTP body for the response.
+  pub fn from_reader(
+    status_code: &'a str,
+    headers: Option<HashMap<&'a str, &'a str>>,
+    reader: impl Read + Send + 'static,
+  ) -> HttpResponse<'a> {
+    let mut response = Self::with_bytes(status_code, headers, None);
+    response.reader = Some(BodyReader(Arc::new(Mutex::new(Box::new(reader)))));
+    response
+  }
+
+  /// Creates a new [`HttpResponse`] object with status `204 No Content`, which has neither
+  /// a body nor a `Content-Length` header.
+  pub fn no_content() -> HttpResponse<'a> {
+    Self::with_bytes("204", Some(HashMap::new()), None)
+  }
+
+  /// Creates a new [`HttpResponse`] object with status `201 Created`, pointing the client
+  /// at the new resource with the `Location` header.
+  ///
+  /// No content type is set: callers returning a body set the one matching it.
+  ///
+  /// # Arguments
+  ///
+  /// * `location`: URL or path of the created resource.
+  /// * `body`: Optional body, usually a representation of the created resource.
+  pub fn created(location: &str, body: Option<String>) -> HttpResponse<'a> {
+    let mut response = Self::new("201", Some(HashMap::new()), body);
+    response.set_header("Location", &url::encode_url(location));
+    response
+  }
+
+  /// Creates a new [`HttpResponse`] object redirecting the client to the given location.
+  ///
+  /// The location is percent-encoded where needed, so input from the client cannot
+  /// inject headers.
+  ///
+  /// # Arguments
+  ///
+  /// * `location`: URL or path the client is redirected to.
+  pub fn redirect(location: &str) -> HttpResponse<'a> {
+    let mut response = Self::with_bytes("302", Some(HashMap::new()), None);
+    response.set_header("Location", &url::encode_url(location));
+    response
+  }
+
+  /// Creates a new [`HttpResponse`] object redirecting the client to the given location
+  /// for good, with status `308 Permanent Redirect`.
+  ///
+  /// Unlike `301` and `302`, clients repeat the request with the same method and body,
+  /// so it suits moved API endpoints. The location is percent-encoded where needed.
+  ///
+  /// # Arguments
+  ///
+  /// * `location`: URL or path the client is redirected to.
+  pub fn permanent_redirect(location: &str) -> HttpResponse<'a> {
+    let mut response = Self::with_bytes("308", Some(HashMap::new()), None);
+    response.set_header("Location", &url::encode_url(location));
+    response
+  }
+
+  /// Creates a new [`HttpResponse`] object redirecting the client to the given path, as
+  /// an absolute URL built from the scheme and `Host` header of the request.
+  ///
+  /// The location stays relative if the request has no valid `Host` header.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request being redirected.
+  /// * `path`: Path the client is redirected to, starting with `/`.
+  pub fn redirect_absolute(request: &HttpRequest, path: &str) -> HttpResponse<'a> {
+    // A host with anything but a name, an address and a port could point elsewhere
+    let valid = |host: &str| {
+      !host.is_empty() && host.bytes().all(|b| b.is_ascii_alphanumeric() || b"-.:[]".contains(&b))
+    };
+    match request.host().filter(|host| valid(host)) {
+      Some(host) => Self::redirect(&format!("{}://{}{}", request.scheme(), host, path)),
+      None => Self::redirect(path),
+    }
+  }
+
+  /// Changes the status of this response, updating its status text.
+  ///
+  /// A code without a known status text gets the generic one of its class, e.g.
+  /// `Client Error` for `499`. A code that is not between 100 and 599 is logged and
+  /// replaced with `500 Internal Server Error`.
+  ///
+  /// # Arguments
+  ///
+  /// * `code`: HTTP status numerical code for the response.
+  pub fn with_status(mut self, code: u16) -> Self {
+    let code = if (100..600).contains(&code) {
+      code
+    } else {
+      warn!("Invalid HTTP status code {}, sending 500 instead", code);
+      500
+    };
+    let code = code.to_string();
+    self.status_text = status_text_of(&code);
+    self.status_code = match STATUSES.iter().find(|(c, _)| *c == code) {
+      Some((known, _)) => Cow::Borrowed(known),
+      None => Cow::Owned(code),
+    };
+    self
+  }
+
+  /// Sets the given HTTP header, replacing any previous value.
+  ///
+  /// # Arguments
+  ///
+  /// * `key`: Name of the HTTP header.
+  /// * `value`: Value of the HTTP header.
+  pub fn set_header(&mut self, key: &str, value: &str) {
+    match self.headers.iter_mut().find(|(k, _)| k.eq_ignore_ascii_case(key)) {
+      Some((_, v)) => *v = value.to_string(),
+      None => self.headers.push((key.to_string(), value.to_string())),
+    }
+  }
+
+  /// Adds the given HTTP header, keeping any previous value, for headers that may be
+  /// repeated such as `Set-Cookie`.
+  ///
+  /// # Arguments
+  ///
+  /// * `key`: Name of the HTTP header.
+  /// * `value`: Value of the HTTP header.
+  pub fn add_header(&mut self, key: &str, value: &str) {
+    self.headers.push((key.to_string(), value.to_string()));
+  }
+
+  /// Removes every value of the given HTTP header, matching its name case-insensitively.
+  ///
+  /// # Arguments
+  ///
+  /// * `key`: Name of the HTTP header.
+  pub fn remove_header(&mut self, key: &str) {
+    self.headers.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+  }
+
+  /// Gets the value of the given HTTP header, matching its name case-insensitively.
+  ///
+  /// # Arguments
+  ///
+  /// * `key`: Name of the HTTP header.
+  pub fn header(&self, key: &str) -> Option<&str> {
+    self.headers
+      .iter()
+      .find(|(k, _)| k.eq_ignore_ascii_case(key))
+      .map(|(_, v)| v.as_str())
+  }
+
+  /// Sets the `Retry-After` header, asking the client to wait for the given delay
+  /// before retrying, in whole seconds.
+  ///
+  /// # Arguments
+  ///
+  /// * `delay`: Delay to wait for.
+  pub fn set_retry_after(&mut self, delay: Duration) {
+    self.set_header("Retry-After", &delay.as_secs().to_string());
+  }
+
+  /// Sets the `Retry-After` header, asking the client to wait until the given time
+  /// before retrying, as an HTTP-date.
+  ///
+  /// Returns an error, leaving the header unset, if the time is in the past.
+  ///
+  /// # Arguments
+  ///
+  /// * `time`: Time to wait until.
+  pub fn set_retry_after_date(&mut self, time: SystemTime) -> std::result::Result<(), SystemTimeError> {
+    time.duration_since(SystemTime::now())?;
+    self.set_header("Retry-After", &date::format(time));
+    Ok(())
+  }
+
+  /// Appends the given chunk of text to the HTTP body, allocating it if absent.
+  ///
+  /// # Arguments
+  ///
+  /// * `chunk`: Text to append to the body.
+  pub fn append_body(&mut self, chunk: &str) {
+    self.body.get_or_insert_with(Vec::new).extend_from_slice(chunk.as_bytes());
+  }
+
+  /// Replaces the HTTP body with the given raw contents.
+  ///
+  /// # Arguments
+  ///
+  /// * `body`: Raw contents of the body.
+  pub fn set_body(&mut self, body: Vec<u8>) {
+    self.body = Some(body);
+    self.reader = None;
+  }
+
+  /// Reads a streamed body whole into this response, so it is sent with a
+  /// `Content-Length` header rather than in chunks, e.g. to clients that do not support
+  /// chunked transfer encoding.
+  pub fn buffer_body(&mut self) -> Result<()> {
+    let Some(BodyReader(reader)) = self.reader.take() else {
+      return Ok(());
+    };
+    let mut body = self.body.take().unwrap_or_default();
+    reader.lock().unwrap_or_else(|e| e.into_inner()).read_to_end(&mut body)?;
+    self.body = Some(body);
+    Ok(())
+  }
+
+  /// Leaves the body out of the serialized response, keeping the headers describing it
+  /// (e.g. `Content-Length`), so this response answers a `HEAD` request.
+  pub fn strip_body(&mut self) {
+    self.head_only = true;
+  }
 
   /// Gets the HTTP version.
   fn version(&self) -> &str {
@@ -90,8 +455,8 @@ impl<'a> HttpResponse<'a> {
   }
 
   /// Gets the HTTP status numerical code.
-  fn status_code(&self) -> &str {
-    self.status_code
+  pub fn status_code(&self) -> &str {
+    &self.status_code
   }
 
   /// Gets the HTTP status text.
@@ -99,26 +464,140 @@ impl<'a> HttpResponse<'a> {
     self.status_text
   }
 
-  /// Gets the HTTP headers as a single text string.
-  fn headers(&self) -> String {
-    let mut header_string: String = "".to_string();
+  /// Gets the HTTP body as text, or an empty string if it is not valid UTF-8.
+  pub fn body(&self) -> &str {
+    std::str::from_utf8(self.body_bytes()).unwrap_or("")
+  }
 
-    for (k, v) in self.headers.as_ref().unwrap() {
-      header_string = format!("{}{}:{}\r\n", header_string, k, v);
+  /// Gets the raw HTTP body.
+  pub fn body_bytes(&self) -> &[u8] {
+    match &self.body {
+      Some(b) => b.as_slice(),
+      None => &[],
     }
-    header_string
   }
 
-  /// Gets the HTTP body.
-  pub fn body(&self) -> &str {
-    match &self.body {
-      Some(b) => b.as_str(),
-      None => "",
+  /// Checks whether the body of this response is streamed from a source.
+  pub fn is_streamed(&self) -> bool {
+    self.reader.is_some()
+  }
+
+  /// Serializes this response (status line, headers and body) as raw bytes.
+  ///
+  /// The `Content-Length` header is always computed from the body: any value set
+  /// manually is left out, so the response never announces a wrong length. A `204`
+  /// or `304` response has neither body nor length. A streamed body is read whole and
+  /// sent in chunks instead. A stripped body is described but left out.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    // Write the head and the body straight into a single buffer sized for both
+    let mut bytes: Vec<u8> = Vec::with_capacity(self.head_len() + self.sent_body().len());
+    let _ = self.write_head(&mut bytes);
+    let _ = self.write_body(&mut bytes);
+    bytes
+  }
+
+  /// Writes this response to the given writer, reading a streamed body as it goes.
+  ///
+  /// # Arguments
+  ///
+  /// * `writer`: Byte stream writer.
+  fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+    let mut head: Vec<u8> = Vec::with_capacity(self.head_len());
+    self.write_head(&mut head)?;
+    writer.write_all(&head)?;
+    self.write_body(writer)
+  }
+
+  /// Checks whether the status of this response forbids a body and its length.
+  fn is_bodiless(&self) -> bool {
+    matches!(self.status_code(), "204" | "304")
+  }
+
+  /// Checks whether the body of this response is sent in chunks, as it is streamed.
+  fn is_chunked(&self) -> bool {
+    self.reader.is_some() && !self.is_bodiless()
+  }
+
+  /// Gets the buffered body that is sent: none for a `204` or `304` response, or once
+  /// the body is stripped.
+  fn sent_body(&self) -> &[u8] {
+    if self.head_only || self.is_bodiless() {
+      &[]
+    } else {
+      self.body_bytes()
+    }
+  }
+
+  /// Gets an upper estimate of the length of the status line and headers, with room
+  /// for the `Content-Length` or `Transfer-Encoding` header.
+  fn head_len(&self) -> usize {
+    let headers: usize = self.headers.iter().map(|(k, v)| k.len() + v.len() + 3).sum();
+    64 + self.status_text().len() + headers
+  }
+
+  /// Writes the status line and headers of this response, up to the blank line ending
+  /// them.
+  ///
+  /// # Arguments
+  ///
+  /// * `head`: Buffer to write to.
+  fn write_head(&self, head: &mut Vec<u8>) -> Result<()> {
+    write!(head, "{} {} {}\r\n", self.version(), self.status_code(), self.status_text())?;
+    for (k, v) in &self.headers {
+      if k.eq_ignore_ascii_case("Content-Length") || k.eq_ignore_ascii_case("Transfer-Encoding") {
+        continue;
+      }
+      write!(head, "{}:{}\r\n", k, v)?;
+    }
+    if self.is_chunked() {
+      head.extend_from_slice(b"Transfer-Encoding: chunked\r\n");
+    } else if !self.is_bodiless() {
+      write!(head, "Content-Length: {}\r\n", self.body_bytes().len())?;
+    }
+    head.extend_from_slice(b"\r\n");
+    Ok(())
+  }
+
+  /// Writes the body of this response to the given writer, reading a streamed body in
+  /// chunks, each with its length, up to the empty last chunk.
+  ///
+  /// # Arguments
+  ///
+  /// * `writer`: Byte stream writer.
+  fn write_body(&self, writer: &mut impl Write) -> Result<()> {
+    let Some(BodyReader(reader)) = self.reader.as_ref().filter(|_| self.is_chunked()) else {
+      return writer.write_all(self.sent_body());
+    };
+    if self.head_only {
+      return Ok(());
+    }
+
+    // Send every chunk read with its length, until the empty last chunk
+    let mut reader = reader.lock().unwrap_or_else(|e| e.into_inner());
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut chunk: Vec<u8> = Vec::with_capacity(CHUNK_SIZE + 16);
+    loop {
+      let n = match reader.read(&mut buffer) {
+        Ok(n) => n,
+        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+        Err(e) => return Err(e),
+      };
+      chunk.clear();
+      write!(chunk, "{:x}\r\n", n)?;
+      chunk.extend_from_slice(&buffer[..n]);
+      chunk.extend_from_slice(b"\r\n");
+      if n == 0 {
+        return writer.write_all(&chunk);
+      }
+      writer.write_all(&chunk)?;
     }
   }
 
   /// Sends this response as a byte stream.
   ///
+  /// The whole response is written and flushed, however few bytes each write accepts,
+  /// or an error is returned.
+  ///
   /// # Arguments
   ///
   /// * `write_stream`: Byte stream writer. Recommended: a TCP stream
@@ -126,15 +605,48 @@ impl<'a> HttpResponse<'a> {
     &self,
     write_stream: &mut impl Write,
   ) -> Result<()> {
-    let response = self.clone();
-    let _ = write!(write_stream, "{}", String::from(response));
-    Ok(())
+    match self.reader {
+      Some(_) => self.write_to(write_stream)?,
+      None => write_stream.write_all(&self.to_bytes())?,
+    }
+    write_stream.flush()
   } // end fn send_response()
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use std::io::{Error, ErrorKind};
+
+  /// Represents a writer accepting at most a few bytes per write, like a slow socket,
+  /// then failing once its capacity is used up.
+  struct TrickleWriter {
+    /// Bytes written so far.
+    written: Vec<u8>,
+    /// Maximum number of bytes accepted by a single write.
+    chunk: usize,
+    /// Number of bytes after which writes fail.
+    capacity: usize,
+    /// Whether the written bytes were flushed.
+    flushed: bool,
+  }
+
+  impl Write for TrickleWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+      if self.written.len() >= self.capacity {
+        return Err(Error::new(ErrorKind::BrokenPipe, "connection closed"));
+      }
+      let n = buf.len().min(self.chunk).min(self.capacity - self.written.len());
+      self.written.extend_from_slice(&buf[..n]);
+      self.flushed = false;
+      Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+      self.flushed = true;
+      Ok(())
+    }
+  }
 
   #[test]
   fn test_response_struct_creation_200() {
@@ -146,14 +658,12 @@ mod tests {
 
     let response_expected = HttpResponse {
       version: "HTTP/1.1",
-      status_code: "200",
+      status_code: Cow::Borrowed("200"),
       status_text: "OK",
-      headers: {
-        let mut h: HashMap<&str, &str> = HashMap::new();
-        h.insert("Content-Type", "text/html");
-        Some(h)
-      },
-      body: Some("Item was shipped on 21st Dec 2020".to_string()),
+      headers: vec![("Content-Type".to_string(), "text/html".to_string())],
+      body: Some("Item was shipped on 21st Dec 2020".into()),
+      reader: None,
+      head_only: false,
     };
 
     assert_eq!(response_actual, response_expected);
@@ -169,31 +679,336 @@ mod tests {
 
     let response_expected = HttpResponse {
       version: "HTTP/1.1",
-      status_code: "404",
+      status_code: Cow::Borrowed("404"),
       status_text: "Not Found",
-      headers: {
-        let mut h: HashMap<&str, &str> = HashMap::new();
-        h.insert("Content-Type", "text/html");
-        Some(h)
-      },
-      body: Some("Item was shipped on 21st Dec 2020".to_string()),
+      headers: vec![("Content-Type".to_string(), "text/html".to_string())],
+      body: Some("Item was shipped on 21st Dec 2020".into()),
+      reader: None,
+      head_only: false,
     };
 
     assert_eq!(response_actual, response_expected);
   }
 
+  #[test]
+  fn test_response_eq_ignores_header_order() {
+    let mut first = HttpResponse::new("200", Some(HashMap::new()), None);
+    first.set_header("X-Request-Id", "1-a");
+    first.set_header("Cache-Control", "no-store");
+    let mut second = HttpResponse::new("200", Some(HashMap::new()), None);
+    second.set_header("Cache-Control", "no-store");
+    second.set_header("X-Request-Id", "1-a");
+
+    assert_eq!(first, second);
+    assert_ne!(first.to_bytes(), second.to_bytes());
+    second.add_header("Cache-Control", "no-store");
+    assert_ne!(first, second);
+  }
+
+  #[test]
+  fn test_response_set_header() {
+    let mut response_actual = HttpResponse::new("200", None, None);
+    response_actual.set_header("Content-Type", "text/plain");
+
+    let http_actual: String = response_actual.into();
+    let http_expected = "HTTP/1.1 200 OK\r\nContent-Type:text/plain\r\nContent-Length: 0\r\n\r\n";
+
+    assert_eq!(http_actual, http_expected);
+  }
+
+  #[test]
+  fn test_response_set_header_replaces_value() {
+    let mut response_actual = HttpResponse::new("200", None, None);
+    response_actual.set_header("X-Request-Id", &format!("{}-{}", 1, "a"));
+    response_actual.set_header("x-request-id", "2-b");
+
+    assert_eq!(response_actual.header("X-Request-Id"), Some("2-b"));
+    assert_eq!(response_actual.headers.len(), 2);
+  }
+
+  #[test]
+  fn test_response_add_header_repeated() {
+    let mut response_actual = HttpResponse::new("200", Some(HashMap::new()), None);
+    response_actual.add_header("Set-Cookie", "a=1");
+    response_actual.add_header("Set-Cookie", "b=2");
+
+    let http_actual: String = response_actual.into();
+    assert_eq!(http_actual, "HTTP/1.1 200 OK\r\nSet-Cookie:a=1\r\nSet-Cookie:b=2\r\nContent-Length: 0\r\n\r\n");
+  }
+
+  #[test]
+  fn test_manual_content_length_overridden() {
+    let mut response_actual = HttpResponse::new("200", None, Some("Hello".to_string()));
+    response_actual.set_header("content-length", "42");
+
+    let http_actual: String = response_actual.into();
+    let http_expected = "HTTP/1.1 200 OK\r\nContent-Type:text/html\r\nContent-Length: 5\r\n\r\nHello";
+
+    assert_eq!(http_actual, http_expected);
+    assert_eq!(http_actual.to_ascii_lowercase().matches("content-length").count(), 1);
+  }
+
+  #[test]
+  fn test_redirect_location_encoded() {
+    let next = "/orders?from=caf\u{e9}\r\nSet-Cookie: x=1";
+    let http_actual: String = HttpResponse::redirect(&format!("/login?next={}", url::percent_encode(next))).into();
+    let injected: String = HttpResponse::redirect(next).into();
+
+    assert_eq!(
+      http_actual,
+      "HTTP/1.1 302 Found\r\nLocation:/login?next=%2Forders%3Ffrom%3Dcaf%C3%A9%0D%0ASet-Cookie%3A%20x%3D1\r\nContent-Length: 0\r\n\r\n"
+    );
+    assert!(!injected.contains("\r\nSet-Cookie"));
+  }
+
+  #[test]
+  fn test_permanent_redirect() {
+    let response = HttpResponse::permanent_redirect("/api/v2/orders");
+
+    assert_eq!(response.status_code(), "308");
+    assert_eq!(response.header("Location"), Some("/api/v2/orders"));
+    let http_actual: String = response.into();
+    assert_eq!(
+      http_actual,
+      "HTTP/1.1 308 Permanent Redirect\r\nLocation:/api/v2/orders\r\nContent-Length: 0\r\n\r\n"
+    );
+  }
+
+  #[test]
+  fn test_redirect_absolute_from_host() {
+    let request: HttpRequest = "GET /old HTTP/1.1\r\nHost: example.com:3000\r\n\r\n".into();
+    let response = HttpResponse::redirect_absolute(&request, "/new path?q=caf\u{e9}");
+
+    assert_eq!(response.status_code(), "302");
+    assert_eq!(response.header("Location"), Some("http://example.com:3000/new%20path?q=caf%C3%A9"));
+  }
+
+  #[test]
+  fn test_redirect_absolute_without_valid_host() {
+    let request: HttpRequest = "GET /old HTTP/1.0\r\n\r\n".into();
+    assert_eq!(HttpResponse::redirect_absolute(&request, "/new").header("Location"), Some("/new"));
+
+    let request: HttpRequest = "GET /old HTTP/1.1\r\nHost: evil.com/phish?\r\n\r\n".into();
+    assert_eq!(HttpResponse::redirect_absolute(&request, "/new").header("Location"), Some("/new"));
+  }
+
+  #[test]
+  fn test_no_content_serialization() {
+    let mut response_actual = HttpResponse::no_content();
+    response_actual.set_header("X-Request-Id", "1-a");
+
+    let http_actual: String = response_actual.into();
+
+    assert_eq!(http_actual, "HTTP/1.1 204 No Content\r\nX-Request-Id:1-a\r\n\r\n");
+  }
+
+  #[test]
+  fn test_created_serialization() {
+    let mut response_actual = HttpResponse::created("/api/shipping/orders/3", Some("{\"order_id\":3}".into()));
+    response_actual.set_header("Content-Type", "application/json");
+
+    assert_eq!(response_actual.status_code(), "201");
+    assert_eq!(response_actual.header("Location"), Some("/api/shipping/orders/3"));
+    let http_actual: String = response_actual.into();
+    assert_eq!(
+      http_actual,
+      "HTTP/1.1 201 Created\r\nLocation:/api/shipping/orders/3\r\nContent-Type:application/json\r\n\
+       Content-Length: 14\r\n\r\n{\"order_id\":3}"
+    );
+  }
+
+  #[test]
+  fn test_retry_after_seconds() {
+    let mut response = HttpResponse::new("503", None, None);
+    response.set_retry_after(Duration::from_millis(120_500));
+
+    assert_eq!(response.header("Retry-After"), Some("120"));
+  }
+
+  #[test]
+  fn test_retry_after_date() {
+    let mut response = HttpResponse::new("503", None, None);
+    let time = SystemTime::now() + Duration::from_secs(3600);
+    response.set_retry_after_date(time).unwrap();
+
+    assert_eq!(response.header("Retry-After"), Some(date::format(time).as_str()));
+  }
+
+  #[test]
+  fn test_retry_after_date_in_past() {
+    let mut response = HttpResponse::new("503", None, None);
+
+    assert!(response.set_retry_after_date(SystemTime::now() - Duration::from_secs(60)).is_err());
+    assert_eq!(response.header("Retry-After"), None);
+  }
+
+  #[test]
+  fn test_remove_header() {
+    let mut response = HttpResponse::default();
+    response.add_header("Set-Cookie", "a=1");
+    response.add_header("set-cookie", "b=2");
+    response.set_header("Vary", "Accept-Encoding");
+
+    response.remove_header("SET-COOKIE");
+
+    assert_eq!(response.header("Set-Cookie"), None);
+    assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+  }
+
+  #[test]
+  fn test_with_status() {
+    let response = HttpResponse::new("200", None, Some("unchanged".into())).with_status(304);
+
+    assert_eq!(response.status_code(), "304");
+    assert_eq!(response.status_text(), "Not Modified");
+    let http_actual: String = response.into();
+    assert_eq!(http_actual, "HTTP/1.1 304 Not Modified\r\nContent-Type:text/html\r\n\r\n");
+  }
+
+  #[test]
+  fn test_with_unknown_status() {
+    let accepted = HttpResponse::default().with_status(202);
+    let unknown = HttpResponse::default().with_status(499);
+
+    assert_eq!(accepted.status_text(), "Accepted");
+    assert_eq!(unknown.status_code(), "499");
+    assert_eq!(unknown.status_text(), "Client Error");
+  }
+
+  #[test]
+  fn test_with_invalid_status() {
+    let response = HttpResponse::default().with_status(600);
+
+    assert_eq!(response.status_code(), "500");
+    assert_eq!(response.status_text(), "Internal Server Error");
+  }
+
+  #[test]
+  fn test_unknown_status_text_agrees() {
+    let built = HttpResponse::new("499", None, None);
+    let changed = HttpResponse::default().with_status(499);
+
+    assert_eq!(built.status_text(), "Client Error");
+    assert_eq!(built.status_text(), changed.status_text());
+  }
+
+  #[test]
+  fn test_send_response_partial_writes() {
+    let response = HttpResponse::new("200", None, Some("Item was shipped on 21st Dec 2020".into()));
+    let mut writer = TrickleWriter { written: Vec::new(), chunk: 3, capacity: usize::MAX, flushed: false };
+
+    response.send_response(&mut writer).unwrap();
+
+    assert_eq!(writer.written, response.to_bytes());
+    assert!(writer.flushed);
+  }
+
+  #[test]
+  fn test_send_response_write_error() {
+    let response = HttpResponse::new("200", None, Some("Item was shipped on 21st Dec 2020".into()));
+    let mut writer = TrickleWriter { written: Vec::new(), chunk: 3, capacity: 10, flushed: false };
+
+    let error = response.send_response(&mut writer).unwrap_err();
+
+    assert_eq!(error.kind(), ErrorKind::BrokenPipe);
+    assert_eq!(writer.written.len(), 10);
+  }
+
+  #[test]
+  fn test_from_json() {
+    let response = HttpResponse::from_json(&serde_json::json!({"order_id": 1})).unwrap();
+
+    let http_actual: String = response.into();
+    assert_eq!(
+      http_actual,
+      "HTTP/1.1 200 OK\r\nContent-Type:application/json;charset=UTF-8\r\nContent-Length: 14\r\n\r\n{\"order_id\":1}"
+    );
+  }
+
+  #[test]
+  fn test_from_json_pretty() {
+    let response = HttpResponse::from_json_pretty(&serde_json::json!({"order_id": 1})).unwrap();
+
+    assert_eq!(response.header("Content-Type"), Some("application/json;charset=UTF-8"));
+    assert_eq!(response.body(), "{\n  \"order_id\": 1\n}");
+  }
+
+  #[test]
+  fn test_from_json_error() {
+    let map: HashMap<Vec<u8>, i32> = HashMap::from([(vec![1], 1)]);
+
+    assert!(HttpResponse::from_json(&map).is_err());
+  }
+
+  #[test]
+  fn test_from_reader_chunked() {
+    let response = HttpResponse::from_reader("200", None, std::io::Cursor::new(b"Item was shipped".to_vec()));
+
+    assert!(response.is_streamed());
+    let http_actual: String = response.into();
+    assert_eq!(
+      http_actual,
+      "HTTP/1.1 200 OK\r\nContent-Type:text/html\r\nTransfer-Encoding: chunked\r\n\r\n10\r\nItem was shipped\r\n0\r\n\r\n"
+    );
+  }
+
+  #[test]
+  fn test_buffer_body_from_reader() {
+    let mut response = HttpResponse::from_reader("200", None, std::io::Cursor::new(b"Item was shipped".to_vec()));
+    response.buffer_body().unwrap();
+
+    assert!(!response.is_streamed());
+    let http_actual: String = response.into();
+    assert_eq!(http_actual, "HTTP/1.1 200 OK\r\nContent-Type:text/html\r\nContent-Length: 16\r\n\r\nItem was shipped");
+  }
+
+  #[test]
+  fn test_send_response_from_reader() {
+    let body = vec![b'a'; CHUNK_SIZE + 5];
+    let response = HttpResponse::from_reader("200", None, std::io::Cursor::new(body));
+    let mut writer = TrickleWriter { written: Vec::new(), chunk: 100, capacity: usize::MAX, flushed: false };
+
+    response.send_response(&mut writer).unwrap();
+
+    let http_actual = String::from_utf8(writer.written).unwrap();
+    let (_, chunks) = http_actual.split_once("\r\n\r\n").unwrap();
+    let expected = format!("2000\r\n{}\r\n5\r\naaaaa\r\n0\r\n\r\n", "a".repeat(CHUNK_SIZE));
+    assert_eq!(chunks, expected);
+    assert!(writer.flushed);
+  }
+
+  #[test]
+  fn test_strip_body_keeps_length() {
+    let mut response = HttpResponse::new("200", None, Some("Hello".to_string()));
+    response.strip_body();
+
+    let http_actual: String = response.into();
+    assert_eq!(http_actual, "HTTP/1.1 200 OK\r\nContent-Type:text/html\r\nContent-Length: 5\r\n\r\n");
+  }
+
+  #[test]
+  fn test_response_append_body() {
+    let mut response_actual = HttpResponse::new("200", None, None);
+    response_actual.append_body("<ul>");
+    response_actual.append_body("<li>index.html</li>");
+    response_actual.append_body("</ul>");
+
+    let http_actual: String = response_actual.into();
+    let http_expected = "HTTP/1.1 200 OK\r\nContent-Type:text/html\r\nContent-Length: 28\r\n\r\n<ul><li>index.html</li></ul>";
+
+    assert_eq!(http_actual, http_expected);
+  }
+
   #[test]
   fn test_http_response_creation() {
     let response_actual = HttpResponse {
       version: "HTTP/1.1",
-      status_code: "404",
+      status_code: Cow::Borrowed("404"),
       status_text: "Not Found",
-      headers: {
-        let mut h: HashMap<&str, &str> = HashMap::new();
-        h.insert("Content-Type", "text/html");
-        Some(h)
-      },
-      body: Some("Item was shipped on 21st Dec 2020".to_string()),
+      headers: vec![("Content-Type".to_string(), "text/html".to_string())],
+      body: Some("Item was shipped on 21st Dec 2020".into()),
+      reader: None,
+      head_only: false,
     };
 
     let http_actual: String = response_actual.into();
@@ -206,14 +1021,12 @@ mod tests {
   fn test_http_response_empty_body() {
     let response_actual = HttpResponse {
       version: "HTTP/1.1",
-      status_code: "404",
+      status_code: Cow::Borrowed("404"),
       status_text: "Not Found",
-      headers: {
-        let mut h: HashMap<&str, &str> = HashMap::new();
-        h.insert("Content-Type", "text/html");
-        Some(h)
-      },
+      headers: vec![("Content-Type".to_string(), "text/html".to_string())],
       body: None,
+      reader: None,
+      head_only: false,
     };
 
     let http_actual: String = String::from(response_actual);
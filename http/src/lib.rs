@@ -1,2 +1,4 @@
+ pub mod date;
  pub mod  httprequest;
- pub mod httpresponse;
\ No newline at end of file
+ pub mod httpresponse;
+ pub mod url;
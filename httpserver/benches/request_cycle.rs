@@ -0,0 +1,34 @@
+//! Measures the time to parse a request, route it and serialize its response, and the
+//! time to serialize the response alone.
+//!
+//! Run with `cargo bench -p httpserver`.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use http::httprequest::HttpRequest;
+use httpserver::{config::ServerConfig, router::Router};
+
+/// Representative request sent by a browser or curl to the API.
+const REQUEST: &str = "GET /api/shipping/orders HTTP/1.1\r\nHost: localhost:3000\r\nUser-Agent: curl/7.81.0\r\nAccept: */*\r\nAccept-Encoding: gzip, deflate\r\n\r\n";
+
+fn request_cycle(c: &mut Criterion) {
+  let router = Router::new(ServerConfig::default());
+  let request: HttpRequest = REQUEST.to_string().into();
+  assert_eq!(router.route(&request).status_code(), "200");
+
+  let mut group = c.benchmark_group("request_cycle");
+  group.throughput(Throughput::Elements(1));
+  group.bench_function("parse -> route -> serialize", |b| {
+    b.iter(|| {
+      let request: HttpRequest = black_box(REQUEST).to_string().into();
+      router.route(&request).to_bytes()
+    })
+  });
+  let response = router.route(&request);
+  group.bench_function("serialize", |b| b.iter(|| black_box(&response).to_bytes()));
+  group.finish();
+}
+
+criterion_group!(benches, request_cycle);
+criterion_main!(benches);
@@ -0,0 +1,471 @@
+//! Response compression, with a self-contained DEFLATE encoder.
+//!
+//! The encoder finds repeated strings with a hash chain over a 32 KiB window and
+//! writes them in a single block of fixed Huffman codes, which every client decodes.
+
+use http::{httprequest::HttpRequest, httpresponse::HttpResponse};
+
+/// Size of the window back-references may reach into.
+const WINDOW_SIZE: usize = 32 * 1024;
+
+/// Shortest string worth a back-reference.
+const MIN_MATCH: usize = 3;
+
+/// Longest string a back-reference may copy.
+const MAX_MATCH: usize = 258;
+
+/// Maximum number of earlier positions compared when looking for the longest match.
+const MAX_CHAIN: usize = 64;
+
+/// Number of bits of the hash indexing the positions by their first bytes.
+const HASH_BITS: u32 = 15;
+
+/// Shortest length of each length code (257 to 285).
+const LENGTH_BASE: [u16; 29] = [
+  3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+  163, 195, 227, 258,
+];
+
+/// Number of extra bits of each length code.
+const LENGTH_EXTRA: [u8; 29] = [
+  0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Shortest distance of each distance code.
+const DISTANCE_BASE: [u16; 30] = [
+  1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+  2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+
+/// Number of extra bits of each distance code.
+const DISTANCE_EXTRA: [u8; 30] = [
+  0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+/// Represents a writer packing bits into bytes, least significant bit first.
+struct BitWriter {
+  /// Bytes written so far.
+  bytes: Vec<u8>,
+  /// Bits not yet written as a whole byte.
+  buffer: u32,
+  /// Number of bits held in the buffer.
+  count: u32,
+}
+
+impl BitWriter {
+  /// Writes the given number of low bits of a value.
+  fn write_bits(&mut self, value: u32, count: u32) {
+    self.buffer |= value << self.count;
+    self.count += count;
+    while self.count >= 8 {
+      self.bytes.push(self.buffer as u8);
+      self.buffer >>= 8;
+      self.count -= 8;
+    }
+  }
+
+  /// Writes a Huffman code, which is packed starting from its most significant bit.
+  fn write_code(&mut self, code: u32, length: u32) {
+    self.write_bits(code.reverse_bits() >> (32 - length), length);
+  }
+
+  /// Writes a literal/length symbol with its fixed Huffman code.
+  fn write_symbol(&mut self, symbol: u16) {
+    let symbol = u32::from(symbol);
+    match symbol {
+      0..=143 => self.write_code(0x30 + symbol, 8),
+      144..=255 => self.write_code(0x190 + symbol - 144, 9),
+      256..=279 => self.write_code(symbol - 256, 7),
+      _ => self.write_code(0xc0 + symbol - 280, 8),
+    }
+  }
+
+  /// Writes a back-reference copying `length` bytes from `distance` bytes before.
+  fn write_match(&mut self, length: usize, distance: usize) {
+    let code = LENGTH_BASE.iter().rposition(|&base| usize::from(base) <= length).unwrap_or(0);
+    self.write_symbol(257 + code as u16);
+    self.write_bits((length - usize::from(LENGTH_BASE[code])) as u32, u32::from(LENGTH_EXTRA[code]));
+
+    let code = DISTANCE_BASE.iter().rposition(|&base| usize::from(base) <= distance).unwrap_or(0);
+    self.write_code(code as u32, 5);
+    self.write_bits((distance - usize::from(DISTANCE_BASE[code])) as u32, u32::from(DISTANCE_EXTRA[code]));
+  }
+
+  /// Writes the remaining bits, padded to a whole byte, and gets the bytes.
+  fn finish(mut self) -> Vec<u8> {
+    if self.count > 0 {
+      self.bytes.push(self.buffer as u8);
+    }
+    self.bytes
+  }
+}
+
+/// Hashes the three bytes starting at the given position.
+fn hash(data: &[u8], i: usize) -> usize {
+  let key = u32::from(data[i]) << 16 | u32::from(data[i + 1]) << 8 | u32::from(data[i + 2]);
+  (key.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+/// Compresses the given data as a raw DEFLATE stream.
+///
+/// # Arguments
+///
+/// * `data`: Data to compress.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+  let mut writer = BitWriter {
+    bytes: Vec::with_capacity(data.len() / 2 + 16),
+    buffer: 0,
+    count: 0,
+  };
+  // A single final block with fixed Huffman codes
+  writer.write_bits(1, 1);
+  writer.write_bits(1, 2);
+
+  // Latest position of each hash, and the previous position with the same hash
+  let mut head: Vec<usize> = vec![usize::MAX; 1 << HASH_BITS];
+  let mut previous: Vec<usize> = vec![usize::MAX; data.len()];
+  let insert = |i: usize, head: &mut [usize], previous: &mut [usize]| {
+    if i + MIN_MATCH <= data.len() {
+      let h = hash(data, i);
+      previous[i] = head[h];
+      head[h] = i;
+    }
+  };
+
+  let mut i = 0;
+  while i < data.len() {
+    // Look for the longest earlier string matching the upcoming bytes
+    let (mut best_length, mut best_distance) = (0, 0);
+    if i + MIN_MATCH <= data.len() {
+      let longest = MAX_MATCH.min(data.len() - i);
+      let mut candidate = head[hash(data, i)];
+      let mut chain = MAX_CHAIN;
+      while candidate != usize::MAX && i - candidate <= WINDOW_SIZE && chain > 0 {
+        let length = (0..longest).take_while(|&k| data[candidate + k] == data[i + k]).count();
+        if length > best_length {
+          best_length = length;
+          best_distance = i - candidate;
+          if length == longest {
+            break;
+          }
+        }
+        candidate = previous[candidate];
+        chain -= 1;
+      }
+    }
+
+    if best_length >= MIN_MATCH {
+      writer.write_match(best_length, best_distance);
+      for k in i..i + best_length {
+        insert(k, &mut head, &mut previous);
+      }
+      i += best_length;
+    } else {
+      writer.write_symbol(u16::from(data[i]));
+      insert(i, &mut head, &mut previous);
+      i += 1;
+    }
+  }
+
+  writer.write_symbol(256);
+  writer.finish()
+}
+
+/// Computes the CRC-32 checksum of the given data, as used by gzip.
+///
+/// # Arguments
+///
+/// * `data`: Data to check.
+pub fn crc32(data: &[u8]) -> u32 {
+  let mut crc: u32 = !0;
+  for &byte in data {
+    crc ^= u32::from(byte);
+    for _ in 0..8 {
+      crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+    }
+  }
+  !crc
+}
+
+/// Compresses the given data in the gzip format.
+///
+/// # Arguments
+///
+/// * `data`: Data to compress.
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+  // Magic number, DEFLATE method, no flags, no modification time, unknown OS
+  let mut bytes: Vec<u8> = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+  bytes.extend(deflate(data));
+  bytes.extend(crc32(data).to_le_bytes());
+  bytes.extend((data.len() as u32).to_le_bytes());
+  bytes
+}
+
+/// Computes the Adler-32 checksum of the given data, as used by zlib.
+///
+/// # Arguments
+///
+/// * `data`: Data to checksum.
+pub fn adler32(data: &[u8]) -> u32 {
+  let (mut a, mut b) = (1u32, 0u32);
+  for &byte in data {
+    a = (a + u32::from(byte)) % 65_521;
+    b = (b + a) % 65_521;
+  }
+  (b << 16) | a
+}
+
+/// Compresses the given data into a zlib stream, the format of the `deflate` content
+/// encoding.
+///
+/// # Arguments
+///
+/// * `data`: Data to compress.
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+  // DEFLATE method with a 32 KiB window, no preset dictionary, fastest level
+  let mut bytes: Vec<u8> = vec![0x78, 0x01];
+  bytes.extend(deflate(data));
+  bytes.extend(adler32(data).to_be_bytes());
+  bytes
+}
+
+/// Gets the quality the client gives to the given content encoding, between `0` (not
+/// acceptable) and `1`, or `None` if it does not mention it nor `*`.
+///
+/// # Arguments
+///
+/// * `request`: HTTP request of the client.
+/// * `name`: Name of the content encoding (e.g. `gzip`).
+fn quality(request: &HttpRequest, name: &str) -> Option<f32> {
+  let mut wildcard = None;
+  for encoding in request.header("Accept-Encoding")?.split(',') {
+    let mut params = encoding.split(';').map(str::trim);
+    let encoding = params.next().unwrap_or("");
+    let quality = params
+      .filter_map(|param| param.replace(' ', "").strip_prefix("q=").and_then(|q| q.parse().ok()))
+      .next()
+      .unwrap_or(1.0);
+    if encoding.eq_ignore_ascii_case(name) {
+      return Some(quality);
+    }
+    if encoding == "*" {
+      wildcard = Some(quality);
+    }
+  }
+  wildcard
+}
+
+/// Checks whether the client accepts responses with the given content encoding.
+///
+/// # Arguments
+///
+/// * `request`: HTTP request of the client.
+/// * `name`: Name of the content encoding (e.g. `gzip`).
+pub fn accepts(request: &HttpRequest, name: &str) -> bool {
+  quality(request, name).is_some_and(|quality| quality > 0.0)
+}
+
+/// Chooses the content encoding the client prefers among the given ones, the first
+/// one winning ties, or `None` if it accepts none of them.
+///
+/// # Arguments
+///
+/// * `request`: HTTP request of the client.
+/// * `supported`: Names of the content encodings the server can produce.
+pub fn negotiate<'a>(request: &HttpRequest, supported: &[&'a str]) -> Option<&'a str> {
+  let mut best: Option<(&str, f32)> = None;
+  for &name in supported {
+    match quality(request, name) {
+      Some(quality) if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) => best = Some((name, quality)),
+      _ => {}
+    }
+  }
+  best.map(|(name, _)| name)
+}
+
+/// Checks whether the given content type is worth compressing.
+///
+/// # Arguments
+///
+/// * `content_type`: Value of the `Content-Type` header, parameters included.
+/// * `compressible`: Compressible media types, `type/*` allowing every subtype.
+fn is_compressible(content_type: &str, compressible: &[String]) -> bool {
+  let media_type = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+  compressible.iter().any(|allowed| match allowed.strip_suffix('*') {
+    Some(prefix) => media_type.starts_with(prefix),
+    None => media_type == *allowed,
+  })
+}
+
+/// Compresses the body of the given response with the encoding the client prefers among
+/// gzip and deflate, if the body is compressible and long enough to be worth it. A
+/// compressed body advertises no support of byte ranges.
+///
+/// # Arguments
+///
+/// * `request`: HTTP request the response answers.
+/// * `response`: HTTP response to compress.
+/// * `threshold`: Minimum length of the body, in bytes.
+/// * `compressible`: Compressible media types, `type/*` allowing every subtype.
+pub fn compress_response(
+  request: &HttpRequest,
+  response: &mut HttpResponse<'_>,
+  threshold: usize,
+  compressible: &[String],
+) {
+  let eligible = response.header("Content-Encoding").is_none()
+    && response.status_code() != "206"
+    && response.body_bytes().len() >= threshold
+    && response.header("Content-Type").is_some_and(|t| is_compressible(t, compressible));
+  if !eligible {
+    return;
+  }
+
+  let (encoding, compressed) = match negotiate(request, &["gzip", "deflate"]) {
+    Some("gzip") => ("gzip", gzip(response.body_bytes())),
+    Some(_) => ("deflate", zlib(response.body_bytes())),
+    None => return,
+  };
+  response.set_body(compressed);
+  response.set_header("Content-Encoding", encoding);
+  response.set_header("Vary", "Accept-Encoding");
+
+  // Byte ranges would be offsets into the uncompressed body, not the one sent
+  if response.header("Accept-Ranges").is_some() {
+    response.set_header("Accept-Ranges", "none");
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+
+  /// Represents a reader of bits, least significant bit first.
+  struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+  }
+
+  impl BitReader<'_> {
+    fn bit(&mut self) -> u32 {
+      let bit = (self.bytes[self.position / 8] >> (self.position % 8)) & 1;
+      self.position += 1;
+      u32::from(bit)
+    }
+
+    fn bits(&mut self, count: u8) -> usize {
+      (0..count).map(|k| (self.bit() as usize) << k).sum()
+    }
+
+    fn code(&mut self, length: u32) -> u32 {
+      (0..length).fold(0, |code, _| code << 1 | self.bit())
+    }
+  }
+
+  /// Decompresses a raw DEFLATE stream made of blocks with fixed Huffman codes.
+  pub fn inflate(bytes: &[u8]) -> Vec<u8> {
+    let mut reader = BitReader { bytes, position: 0 };
+    let mut data: Vec<u8> = Vec::new();
+    loop {
+      let last = reader.bits(1) == 1;
+      assert_eq!(reader.bits(2), 1, "only fixed Huffman blocks are supported");
+      loop {
+        // Read the literal/length symbol, whose code has 7 to 9 bits
+        let mut code = reader.code(7);
+        let symbol = if code <= 0x17 {
+          256 + code
+        } else {
+          code = code << 1 | reader.bit();
+          match code {
+            0x30..=0xbf => code - 0x30,
+            0xc0..=0xc7 => 280 + code - 0xc0,
+            _ => 144 + (code << 1 | reader.bit()) - 0x190,
+          }
+        } as usize;
+
+        match symbol {
+          0..=255 => data.push(symbol as u8),
+          256 => break,
+          _ => {
+            let code = symbol - 257;
+            let length = usize::from(LENGTH_BASE[code]) + reader.bits(LENGTH_EXTRA[code]);
+            let code = reader.code(5) as usize;
+            let distance = usize::from(DISTANCE_BASE[code]) + reader.bits(DISTANCE_EXTRA[code]);
+            for _ in 0..length {
+              data.push(data[data.len() - distance]);
+            }
+          }
+        }
+      }
+      if last {
+        return data;
+      }
+    }
+  }
+
+  /// Decompresses a gzip member, checking its length and checksum.
+  pub fn gunzip(bytes: &[u8]) -> Vec<u8> {
+    assert_eq!(&bytes[..3], [0x1f, 0x8b, 8]);
+    let data = inflate(&bytes[10..bytes.len() - 8]);
+    let trailer = &bytes[bytes.len() - 8..];
+    assert_eq!(trailer[..4], crc32(&data).to_le_bytes());
+    assert_eq!(trailer[4..], (data.len() as u32).to_le_bytes());
+    data
+  }
+
+  /// Decompresses a zlib stream, checking its checksum.
+  pub fn unzlib(bytes: &[u8]) -> Vec<u8> {
+    assert_eq!(((u16::from(bytes[0]) << 8) | u16::from(bytes[1])) % 31, 0);
+    let data = inflate(&bytes[2..bytes.len() - 4]);
+    assert_eq!(bytes[bytes.len() - 4..], adler32(&data).to_be_bytes());
+    data
+  }
+
+  /// Request from a client sending the given Accept-Encoding header.
+  fn accepting(encodings: &str) -> HttpRequest {
+    format!("GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: {}\r\n\r\n", encodings).into()
+  }
+
+  #[test]
+  fn test_adler32_check_value() {
+    assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    assert_eq!(adler32(b""), 1);
+  }
+
+  #[test]
+  fn test_zlib_round_trip() {
+    let repeated = "<li>order</li>".repeat(500);
+    for data in [b"".as_slice(), b"abcabcabcabcabc", repeated.as_bytes()] {
+      assert_eq!(unzlib(&zlib(data)), data);
+    }
+  }
+
+  #[test]
+  fn test_negotiate_preferred_encoding() {
+    let supported = ["gzip", "deflate"];
+    assert_eq!(negotiate(&accepting("gzip;q=0.5, deflate"), &supported), Some("deflate"));
+    assert_eq!(negotiate(&accepting("deflate;q=0.5, gzip"), &supported), Some("gzip"));
+    assert_eq!(negotiate(&accepting("deflate, gzip"), &supported), Some("gzip"));
+    assert_eq!(negotiate(&accepting("br, *;q=0.1"), &supported), Some("gzip"));
+    assert_eq!(negotiate(&accepting("gzip;q=0, deflate;q=0"), &supported), None);
+    assert_eq!(negotiate(&accepting("br"), &supported), None);
+  }
+
+  #[test]
+  fn test_crc32_check_value() {
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    assert_eq!(crc32(b""), 0);
+  }
+
+  #[test]
+  fn test_gzip_round_trip() {
+    let repeated = "<li>order</li>".repeat(500);
+    let mixed: Vec<u8> = (0..20_000u32).map(|i| (i * i % 251) as u8).collect();
+    let cases: [&[u8]; 5] = [b"", b"a", b"abcabcabcabcabc", repeated.as_bytes(), &mixed];
+
+    for data in cases {
+      assert_eq!(gunzip(&gzip(data)), data);
+    }
+    assert!(gzip(repeated.as_bytes()).len() < repeated.len() / 10);
+  }
+}
@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use http::httpresponse::HttpResponse;
+
+use crate::{
+  handlers::{DefaultRoot, DEFAULT_ROBOTS_TXT},
+  metrics::DEFAULT_DURATION_BUCKETS,
+};
+
+/// Represents the configuration shared by the server and its router.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+  /// Whether HTTP/0.9 simple requests (without version, e.g. `GET /path`) are served,
+  /// with a bare body as response. Disabled by default, rejecting them with `400`.
+  pub allow_http_0_9: bool,
+  /// Whether `TRACE` requests are served. Disabled by default.
+  pub enable_trace: bool,
+  /// Whether `CONNECT` requests are served. Disabled by default.
+  pub enable_connect: bool,
+  /// HTTP status numerical code used to reject disabled methods.
+  pub disabled_method_status: &'static str,
+  /// Maximum length of a request body, in bytes, unless its route sets another one.
+  /// Longer bodies are rejected with `413` without being read.
+  pub max_body_size: usize,
+  /// Length over which a request body is written to a temporary file rather than kept in
+  /// memory, in bytes, e.g. for large uploads. Disabled by default.
+  pub body_spill_threshold: Option<usize>,
+  /// Maximum length of the requested path and query once percent-decoded, in bytes.
+  /// Longer ones are rejected with `414`.
+  pub max_uri_length: usize,
+  /// Maximum number of requests served over a single keep-alive connection.
+  pub keep_alive_max_requests: usize,
+  /// Maximum time a keep-alive connection may stay idle between requests.
+  pub keep_alive_timeout: Duration,
+  /// Maximum time a client may stay silent once it started sending a request, e.g. before
+  /// ending its headers with a blank line. Such a request is answered with `408`.
+  pub read_timeout: Duration,
+  /// Capacity of the buffer each connection is read through, in bytes. Larger buffers
+  /// take fewer reads for large bodies, smaller ones save memory under many connections.
+  pub read_buffer_capacity: usize,
+  /// Maximum time spent on a single request, from its first byte to the end of its
+  /// response. A request handled for longer is not responded to: its connection is
+  /// closed. Unlimited by default.
+  pub request_timeout: Option<Duration>,
+  /// Whether responses kept alive announce the idle timeout and the number of requests
+  /// left with a `Keep-Alive` header (e.g. `Keep-Alive: timeout=5, max=99`).
+  pub keep_alive_header: bool,
+  /// Whether the server runs behind a proxy whose `X-Forwarded-For` and `X-Forwarded-Proto`
+  /// headers give the client address and scheme. Disabled by default, as clients could
+  /// otherwise spoof them.
+  pub trust_proxy: bool,
+  /// Scheme and host every request is redirected to with `301` when it was received with
+  /// another one, keeping its path and query, e.g. `https://www.example.com`. The scheme
+  /// is only known to be `https` behind a trusted proxy. None by default.
+  pub canonical_origin: Option<String>,
+  /// Whether changes to the shipping orders are written back to the data file.
+  /// Disabled by default, keeping changes in memory only.
+  pub flush_orders: bool,
+  /// Path segments the static handler refuses to serve, in addition to hidden ones.
+  pub denied_paths: Vec<String>,
+  /// Hidden path segments (starting with a dot) the static handler may serve.
+  pub allowed_hidden_paths: Vec<String>,
+  /// HTTP status numerical code used to refuse denied and hidden paths. Set it to
+  /// `404` to hide whether they exist.
+  pub denied_path_status: &'static str,
+  /// Charset announced in the content type of text responses.
+  pub charset: &'static str,
+  /// Name of the file served for the home page and directories.
+  pub index_file: String,
+  /// Response to the home page when the public directory has no index file, e.g. a
+  /// welcome page or a redirection to `/health`. An empty page by default.
+  pub default_root: Option<DefaultRoot>,
+  /// Delay clients are asked to wait for in the `Retry-After` header of maintenance responses.
+  pub maintenance_retry_after: Duration,
+  /// Headers added to every response that does not set them already, e.g. security
+  /// headers such as `X-Content-Type-Options: nosniff`. None by default.
+  pub default_headers: Vec<(String, String)>,
+  /// Contents of `/robots.txt` when the public directory has none. Allows every
+  /// crawler by default; `None` answers `404` instead.
+  pub robots_txt: Option<String>,
+  /// Contents of `/sitemap.xml` when the public directory has none. None by default.
+  pub sitemap_xml: Option<String>,
+  /// Path of the icon file served at `/favicon.ico`, read once at startup. A built-in
+  /// transparent icon is served by default.
+  pub favicon: Option<String>,
+  /// Number of threads serving connections.
+  pub worker_threads: usize,
+  /// Maximum number of connections waiting for a thread once they are all busy.
+  /// Connections beyond it are answered with `503` and closed.
+  pub queue_capacity: usize,
+  /// Maximum number of connections open at the same time, served or waiting for a thread.
+  /// Connections beyond it are answered with `503` and closed. Unlimited by default.
+  pub max_connections: Option<usize>,
+  /// Delay clients are asked to wait for in the `Retry-After` header when the server is busy.
+  pub busy_retry_after: Duration,
+  /// Minimum length of a body compressed with gzip for the clients accepting it, in bytes.
+  pub compression_threshold: usize,
+  /// Media types worth compressing, `type/*` allowing every subtype. Already compressed
+  /// types such as images and archives gain nothing from it.
+  pub compressible_types: Vec<String>,
+  /// Whether `/__debug/echo` answers with the request as parsed, in JSON, to troubleshoot
+  /// clients. Disabled by default, as it reflects every header back.
+  pub debug_echo: bool,
+  /// Whether `/metrics` answers with the histogram of request durations, in the Prometheus
+  /// text format. Disabled by default.
+  pub enable_metrics: bool,
+  /// Upper bounds of the buckets of the request duration histogram.
+  pub metrics_buckets: Vec<Duration>,
+  /// Whether requests keep a copy of their original text for the handlers, e.g. to log
+  /// malformed requests. Disabled by default to save the memory.
+  pub keep_raw_requests: bool,
+}
+
+impl ServerConfig {
+  /// Adds the configured default headers the given response does not set already, so
+  /// they reach every response, whether produced by the router, a connection or the server.
+  ///
+  /// # Arguments
+  ///
+  /// * `response`: HTTP response about to be sent.
+  pub fn apply_default_headers(&self, response: &mut HttpResponse) {
+    for (key, value) in &self.default_headers {
+      if response.header(key).is_none() {
+        response.set_header(key, value);
+      }
+    }
+  }
+}
+
+impl Default for ServerConfig {
+  fn default() -> Self {
+    Self {
+      allow_http_0_9: false,
+      enable_trace: false,
+      enable_connect: false,
+      disabled_method_status: "405",
+      max_body_size: 1024 * 1024,
+      body_spill_threshold: None,
+      max_uri_length: 8 * 1024,
+      keep_alive_max_requests: 100,
+      keep_alive_timeout: Duration::from_secs(5),
+      read_timeout: Duration::from_secs(5),
+      read_buffer_capacity: 8 * 1024,
+      request_timeout: None,
+      keep_alive_header: true,
+      trust_proxy: false,
+      canonical_origin: None,
+      flush_orders: false,
+      denied_paths: vec![".git".to_string(), ".env".to_string(), ".htpasswd".to_string()],
+      allowed_hidden_paths: vec![".well-known".to_string()],
+      denied_path_status: "403",
+      charset: "utf-8",
+      index_file: "index.html".to_string(),
+      default_root: None,
+      maintenance_retry_after: Duration::from_secs(120),
+      default_headers: Vec::new(),
+      robots_txt: Some(DEFAULT_ROBOTS_TXT.to_string()),
+      sitemap_xml: None,
+      favicon: None,
+      worker_threads: 8,
+      queue_capacity: 64,
+      max_connections: None,
+      busy_retry_after: Duration::from_secs(1),
+      compression_threshold: 1024,
+      compressible_types: ["text/*", "application/json", "application/xml", "application/javascript", "image/svg+xml"]
+        .iter()
+        .map(|t| t.to_string())
+        .collect(),
+      debug_echo: false,
+      enable_metrics: false,
+      metrics_buckets: DEFAULT_DURATION_BUCKETS.to_vec(),
+      keep_raw_requests: false,
+    }
+  }
+}
@@ -0,0 +1,1038 @@
+use std::{
+  env,
+  fs::{self, File},
+  io::{self, BufRead, BufReader, Read, Write},
+  net::{Shutdown, TcpStream},
+  panic::{self, AssertUnwindSafe},
+  path::{Path, PathBuf},
+  process,
+  sync::atomic::{AtomicUsize, Ordering},
+  time::{Duration, Instant},
+};
+
+use http::{
+  httprequest::{HttpRequest, Method, Resource, Version},
+  httpresponse::HttpResponse,
+};
+use log::{debug, error, info, warn};
+
+use crate::router::Router;
+
+/// Maximum time spent discarding the data a client sent after the last response.
+const LINGER_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Maximum number of bytes discarded after the last response.
+const LINGER_MAX_BYTES: u64 = 64 * 1024;
+
+/// Maximum length of the request line and headers of a request, in bytes.
+const MAX_HEAD_LENGTH: usize = 64 * 1024;
+
+/// Number of request bodies written to temporary files so far, naming the next one.
+static SPILLED_BODIES: AtomicUsize = AtomicUsize::new(0);
+
+/// Represents a request body written to a temporary file, removed once dropped.
+struct SpilledBody {
+  /// Path of the temporary file.
+  path: PathBuf,
+}
+
+impl SpilledBody {
+  /// Writes the next bytes of the given reader to a new temporary file.
+  ///
+  /// # Arguments
+  ///
+  /// * `reader`: Buffered reader over the client connection.
+  /// * `length`: Length of the body, in bytes.
+  fn read_from(reader: &mut impl BufRead, length: usize) -> io::Result<Self> {
+    let count = SPILLED_BODIES.fetch_add(1, Ordering::Relaxed);
+    let path = env::temp_dir().join(format!("httpserver-body-{}-{}", process::id(), count));
+    let body = Self { path };
+
+    let mut file = File::create_new(&body.path)?;
+    let read = io::copy(&mut reader.take(length as u64), &mut file)?;
+    // A body cut short is malformed, rather than handed truncated to the handler
+    if read < length as u64 {
+      let message = format!("the body ended after {} of {} bytes", read, length);
+      return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    Ok(body)
+  }
+
+  /// Gets the path of the temporary file.
+  fn path(&self) -> &Path {
+    &self.path
+  }
+}
+
+impl Drop for SpilledBody {
+  fn drop(&mut self) {
+    if let Err(e) = fs::remove_file(&self.path) {
+      warn!("Failed to remove the temporary file {}: {}", self.path.display(), e);
+    }
+  }
+}
+
+/// Serves the requests sent over a single client connection.
+///
+/// The connection is kept alive until the client closes it, stays idle for
+/// longer than the configured timeout or reaches the configured maximum
+/// number of requests.
+///
+/// A client that stops sending in the middle of a request, e.g. before the blank line
+/// ending its headers, is answered with `408 Request Timeout` once the read timeout expires.
+///
+/// A body longer than the configured spill threshold is written to a temporary file
+/// rather than kept in memory, and the file is removed once the request is responded to.
+///
+/// A request exceeding the configured timeout closes the connection without a response.
+/// Handlers cannot be interrupted, so the timeout is checked once the handler returns,
+/// and bounds the time left to write the response.
+///
+/// Requests may be pipelined, i.e. sent without waiting for the previous responses:
+/// each one is fully processed and responded to before the next one is read, so the
+/// responses are sent in the order the requests were received.
+///
+/// # Arguments
+///
+/// * `stream`: TCP stream of the client connection.
+/// * `router`: Router used to dispatch the requests.
+pub fn serve(mut stream: TcpStream, router: &Router) -> io::Result<()> {
+  let config = router.config();
+
+  let mut reader = buffered_reader(&stream, config.read_buffer_capacity)?;
+  let mut buffer: Vec<u8> = Vec::new();
+  let mut served: usize = 0;
+
+  loop {
+    // The time spent on a request starts with its first byte, not with the idle wait before it
+    stream.set_read_timeout(Some(config.keep_alive_timeout))?;
+    reader.fill_buf()?;
+    let started = Instant::now();
+    stream.set_read_timeout(Some(config.read_timeout))?;
+
+    let max_body_size = |method, path: &str| router.max_body_size(method, path);
+    let read = read_head(&mut reader, &mut buffer, max_body_size).and_then(|length| match length {
+      Some(length) if config.body_spill_threshold.is_some_and(|threshold| length > threshold) => {
+        SpilledBody::read_from(&mut reader, length).map(|body| Some(Some(body)))
+      }
+      Some(length) => read_body(&mut reader, &mut buffer, length).map(|_| Some(None)),
+      None => Ok(None),
+    });
+    let spilled = match read {
+      Ok(Some(spilled)) => spilled,
+      Ok(None) => break,
+      // A body over the limit is not read, so the connection cannot be reused
+      Err(e) if e.kind() == io::ErrorKind::FileTooLarge => {
+        debug!("Rejecting a request: {}", e);
+        let mut response = HttpResponse::new("413", None, None);
+        response.set_header("Connection", "close");
+        config.apply_default_headers(&mut response);
+        response.send_response(&mut stream)?;
+        linger(&mut stream, &mut reader);
+        return Ok(());
+      }
+      // A request that is not valid UTF-8 or cannot be framed is rejected, closing the connection
+      Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+        debug!("Rejecting a malformed request: {}", e);
+        let mut response = HttpResponse::new("400", None, None);
+        response.set_header("Connection", "close");
+        config.apply_default_headers(&mut response);
+        response.send_response(&mut stream)?;
+        linger(&mut stream, &mut reader);
+        return Ok(());
+      }
+      // A client that stops sending in the middle of a request is told so before closing
+      Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+        debug!("Rejecting a request not sent within {} ms", config.read_timeout.as_millis());
+        let mut response = HttpResponse::new("408", None, None);
+        response.set_header("Connection", "close");
+        config.apply_default_headers(&mut response);
+        response.send_response(&mut stream)?;
+        return Ok(());
+      }
+      Err(e) => return Err(e),
+    };
+    served += 1;
+
+    let mut request = match config.keep_raw_requests {
+      true => HttpRequest::with_raw(&buffer),
+      false => HttpRequest::from(buffer.as_slice()),
+    };
+    request.peer_addr = stream.peer_addr().ok();
+    request.trust_proxy = config.trust_proxy;
+    request.body_file = spilled.as_ref().map(|body| body.path().to_path_buf());
+    // The body framed by Content-Length is consumed but has no meaning for GET and HEAD
+    if matches!(request.method, Method::GET | Method::HEAD) && !request.msg_body.is_empty() {
+      debug!("Discarding the {} bytes body of a {:?} request", request.msg_body.len(), request.method);
+      request.msg_body.clear();
+    }
+    if matches!(request.method, Method::GET | Method::HEAD) {
+      request.body_file = None;
+    }
+    let mut response = router.route(&request);
+
+    // Log the request along with the identifier it was assigned
+    let Resource::Path(path) = &request.resource;
+    info!(
+      "[{}] {} {:?} {} {}",
+      response.header("X-Request-Id").unwrap_or("-"),
+      request.client_ip().map_or("-".to_string(), |ip| ip.to_string()),
+      request.method,
+      path,
+      response.status_code()
+    );
+
+    if let Some(timeout) = config.request_timeout {
+      let elapsed = started.elapsed();
+      if elapsed >= timeout {
+        warn!(
+          "[{}] {:?} {} took {} ms, over the request timeout of {} ms: closing the connection",
+          response.header("X-Request-Id").unwrap_or("-"),
+          request.method,
+          path,
+          elapsed.as_millis(),
+          timeout.as_millis()
+        );
+        let _ = stream.shutdown(Shutdown::Both);
+        break;
+      }
+      stream.set_write_timeout(Some(timeout - elapsed))?;
+    }
+
+    // A simple response of HTTP/0.9 is the bare body, ended by closing the connection
+    if request.version == Version::V0_9 && response.status_code() == "200" {
+      stream.write_all(response.body_bytes())?;
+      linger(&mut stream, &mut reader);
+      break;
+    }
+
+    // The computed length is sent instead of a wrong one set by the handler
+    if let Some(length) = response.header("Content-Length") {
+      if !response.is_streamed() && length.trim().parse() != Ok(response.body_bytes().len()) {
+        warn!(
+          "Ignoring the Content-Length {} set for a body of {} bytes",
+          length,
+          response.body_bytes().len()
+        );
+      }
+    }
+
+    // Announce the last response so the client does not reuse the connection, which
+    // cannot be trusted either after a request line that could not be parsed
+    let last = served >= config.keep_alive_max_requests
+      || request.version == Version::UNINITIALIZED
+      || !request.wants_keep_alive();
+    if last {
+      response.set_header("Connection", "close");
+    } else {
+      if request.version == Version::V1_0 {
+        response.set_header("Connection", "keep-alive");
+      }
+      if config.keep_alive_header {
+        let hints = format!(
+          "timeout={}, max={}",
+          config.keep_alive_timeout.as_secs(),
+          config.keep_alive_max_requests - served
+        );
+        response.set_header("Keep-Alive", &hints);
+      }
+    }
+
+    // HTTP/1.0 has no chunked transfer encoding, so a streamed body is sent whole
+    if request.version == Version::V1_0 && response.is_streamed() {
+      response.buffer_body()?;
+    }
+
+    response.send_response(&mut stream)?;
+
+    if last {
+      linger(&mut stream, &mut reader);
+      break;
+    }
+  }
+
+  Ok(())
+} // end fn serve()
+
+/// Creates the buffered reader the requests of a connection are read through.
+///
+/// # Arguments
+///
+/// * `stream`: TCP stream of the client connection.
+/// * `capacity`: Capacity of the buffer, in bytes.
+fn buffered_reader(stream: &TcpStream, capacity: usize) -> io::Result<BufReader<TcpStream>> {
+  Ok(BufReader::with_capacity(capacity, stream.try_clone()?))
+}
+
+/// Closes the sending side of a connection, then discards what the client sent after
+/// the last request it gets a response to.
+///
+/// Closing a socket with unread data resets the connection, which may destroy the last
+/// response before the client reads it.
+///
+/// # Arguments
+///
+/// * `stream`: TCP stream of the client connection.
+/// * `reader`: Buffered reader over the client connection, holding any data read ahead.
+fn linger(stream: &mut TcpStream, reader: &mut impl Read) {
+  if stream.shutdown(Shutdown::Write).is_ok() && stream.set_read_timeout(Some(LINGER_TIMEOUT)).is_ok() {
+    let _ = io::copy(&mut reader.take(LINGER_MAX_BYTES), &mut io::sink());
+  }
+}
+
+/// Serves a client connection, confining any failure to it.
+///
+/// I/O errors close the connection and are logged, and so are panics raised while
+/// processing one of its requests, so neither reaches the accept loop nor the
+/// thread serving the connection.
+///
+/// # Arguments
+///
+/// * `stream`: TCP stream of the client connection.
+/// * `router`: Router used to dispatch the requests.
+pub fn serve_isolated(stream: TcpStream, router: &Router) {
+  match panic::catch_unwind(AssertUnwindSafe(|| serve(stream, router))) {
+    Ok(Ok(())) => {}
+    Ok(Err(e)) => debug!("Connection closed: {}", e),
+    Err(_) => error!("Connection dropped after a panic while serving it"),
+  }
+}
+
+/// Reads the raw bytes of the next request (header section and body) from the stream.
+///
+/// The request is read into the given buffer, which is cleared but not freed, so a
+/// connection can reuse its allocation across requests. Returns `None` when the client
+/// closed the connection before sending a new request.
+///
+/// The body is delimited by the `Content-Length` header, whatever the method, so the
+/// next request starts right after it. A malformed or conflicting `Content-Length` is
+/// an `InvalidData` error, as the end of the request cannot be told, and so is a header
+/// section that is not valid UTF-8, not ended by a blank line or too long, a body framed
+/// by `Transfer-Encoding`, or a body shorter than declared. The body may be binary.
+///
+/// # Arguments
+///
+/// * `reader`: Buffered reader over the client connection.
+/// * `buffer`: Buffer to read the request into.
+pub fn read_request<'b>(
+  reader: &mut impl BufRead,
+  buffer: &'b mut Vec<u8>,
+) -> io::Result<Option<&'b [u8]>> {
+  read_request_within(reader, buffer, |_, _| usize::MAX)
+}
+
+/// Reads the raw bytes of the next request like [`read_request`], refusing a body longer
+/// than the limit of its method and path with a `FileTooLarge` error, before reading it.
+///
+/// # Arguments
+///
+/// * `reader`: Buffered reader over the client connection.
+/// * `buffer`: Buffer to read the request into.
+/// * `max_body_size`: Function giving the maximum body length of a method and path.
+pub fn read_request_within<'b>(
+  reader: &mut impl BufRead,
+  buffer: &'b mut Vec<u8>,
+  max_body_size: impl Fn(Method, &str) -> usize,
+) -> io::Result<Option<&'b [u8]>> {
+  match read_head(reader, buffer, max_body_size)? {
+    Some(body_length) => {
+      read_body(reader, buffer, body_length)?;
+      Ok(Some(buffer))
+    }
+    None => Ok(None),
+  }
+} // end fn read_request_within()
+
+/// Reads the request line and headers of the next request into the given buffer, and
+/// returns the length of the body they declare, or `None` once the client closed.
+///
+/// Only `Content-Length` frames bodies: a request with a `Transfer-Encoding` header is
+/// refused, so that a body this server and a proxy in front of it would frame differently
+/// cannot smuggle a request. So is a header section over [`MAX_HEAD_LENGTH`].
+///
+/// # Arguments
+///
+/// * `reader`: Buffered reader over the client connection.
+/// * `buffer`: Buffer to read the header section into.
+/// * `max_body_size`: Function giving the maximum body length of a method and path.
+fn read_head(
+  reader: &mut impl BufRead,
+  buffer: &mut Vec<u8>,
+  max_body_size: impl Fn(Method, &str) -> usize,
+) -> io::Result<Option<usize>> {
+  buffer.clear();
+  let mut content_length: Option<usize> = None;
+
+  // Read the request line and headers up to the blank line
+  loop {
+    let line_start = buffer.len();
+    let remaining = (MAX_HEAD_LENGTH - line_start) as u64;
+    if reader.by_ref().take(remaining + 1).read_until(b'\n', buffer)? == 0 {
+      if buffer.is_empty() {
+        return Ok(None);
+      }
+      return Err(io::Error::new(io::ErrorKind::InvalidData, "the header section ended without a blank line"));
+    }
+    if buffer.len() > MAX_HEAD_LENGTH {
+      let message = format!("the header section is over the limit of {} bytes", MAX_HEAD_LENGTH);
+      return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+
+    let text = String::from_utf8_lossy(&buffer[line_start..]);
+    let text = text.trim_end();
+    if text.is_empty() {
+      break;
+    }
+    if let Some((key, value)) = text.split_once(':') {
+      if key.trim().eq_ignore_ascii_case("Content-Length") {
+        let length: usize = value.trim().parse().map_err(|_| {
+          io::Error::new(io::ErrorKind::InvalidData, format!("invalid Content-Length {:?}", value.trim()))
+        })?;
+        if content_length.is_some_and(|previous| previous != length) {
+          return Err(io::Error::new(io::ErrorKind::InvalidData, "conflicting Content-Length headers"));
+        }
+        content_length = Some(length);
+      } else if key.trim().eq_ignore_ascii_case("Transfer-Encoding") {
+        let message = format!("unsupported Transfer-Encoding {:?}", value.trim());
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+      }
+    }
+  }
+
+  let head = std::str::from_utf8(buffer).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+  let body_length = content_length.unwrap_or(0);
+  if body_length > 0 {
+    let mut request_line = head.split_whitespace();
+    let method = Method::from(request_line.next().unwrap_or(""));
+    let resource = request_line.next().unwrap_or("");
+    let path = resource.split_once('?').map_or(resource, |(path, _)| path);
+    let limit = max_body_size(method, path);
+    if body_length > limit {
+      let message = format!("a body of {} bytes is over the limit of {} bytes", body_length, limit);
+      return Err(io::Error::new(io::ErrorKind::FileTooLarge, message));
+    }
+  }
+
+  Ok(Some(body_length))
+} // end fn read_head()
+
+/// Reads exactly the given number of body bytes at the end of the buffer, leaving any
+/// following request unread.
+///
+/// # Arguments
+///
+/// * `reader`: Buffered reader over the client connection.
+/// * `buffer`: Buffer holding the header section of the request.
+/// * `body_length`: Length of the body, in bytes.
+fn read_body(reader: &mut impl BufRead, buffer: &mut Vec<u8>, body_length: usize) -> io::Result<()> {
+  let head_length = buffer.len();
+  buffer.resize(head_length + body_length, 0);
+  let mut read = 0;
+  while read < body_length {
+    match reader.read(&mut buffer[head_length + read..]) {
+      // A body cut short is malformed, rather than handed truncated to the handler
+      Ok(0) => {
+        let message = format!("the body ended after {} of {} bytes", read, body_length);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+      }
+      Ok(n) => read += n,
+      Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+      Err(e) => return Err(e),
+    }
+  }
+
+  Ok(())
+} // end fn read_body()
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{
+    io::Write,
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+  };
+
+  use crate::{
+    config::ServerConfig,
+    logging,
+  };
+  use log::Level;
+
+  /// Reads the next message from the stream as text.
+  fn read_text<'b>(reader: &mut impl BufRead, buffer: &'b mut Vec<u8>) -> io::Result<Option<&'b str>> {
+    Ok(read_request(reader, buffer)?.map(|message| std::str::from_utf8(message).unwrap()))
+  }
+
+  /// Starts a server for a single connection and returns the client side of it.
+  fn connect(config: ServerConfig) -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      let _ = serve(stream, &Router::new(config));
+    });
+
+    TcpStream::connect(address).unwrap()
+  }
+
+  #[test]
+  fn test_read_request_with_body() {
+    let raw = "POST /x HTTP/1.1\r\nContent-Length: 5\r\n\r\nHelloGET /y HTTP/1.1\r\n\r\n";
+    let mut reader = io::Cursor::new(raw.as_bytes());
+    let mut buffer: Vec<u8> = Vec::new();
+
+    let first = read_text(&mut reader, &mut buffer).unwrap().unwrap().to_string();
+    let second = read_text(&mut reader, &mut buffer).unwrap().unwrap().to_string();
+
+    assert_eq!(first, "POST /x HTTP/1.1\r\nContent-Length: 5\r\n\r\nHello");
+    assert_eq!(second, "GET /y HTTP/1.1\r\n\r\n");
+    assert_eq!(read_text(&mut reader, &mut buffer).unwrap(), None);
+  }
+
+  #[test]
+  fn test_read_request_short_body() {
+    let raw = "POST /x HTTP/1.1\r\nContent-Length: 100\r\n\r\n".to_string() + &"a".repeat(40);
+    let mut reader = io::Cursor::new(raw.as_bytes());
+
+    let error = read_request(&mut reader, &mut Vec::new()).unwrap_err();
+
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(error.to_string(), "the body ended after 40 of 100 bytes");
+  }
+
+  /// Sends a body of 100 bytes to the given path of a router and returns the response.
+  fn post_body(router: &Router, path: &str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let raw = format!("POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: 100\r\n\r\n{}", path, "a".repeat(100));
+    client.write_all(raw.as_bytes()).unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+
+    let (stream, _) = listener.accept().unwrap();
+    serve(stream, router).unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    response
+  }
+
+  #[test]
+  fn test_body_limit_per_route() {
+    let mut router = Router::new(ServerConfig { max_body_size: 64, ..ServerConfig::default() });
+    let echo = |request: &HttpRequest| HttpResponse::with_bytes("200", None, Some(request.body_bytes().to_vec()));
+    router.add_route(Method::POST, "/comment", echo);
+    router.add_route_with_max_body(Method::POST, "/avatar", 16, echo);
+    router.add_route_with_max_body(Method::POST, "/upload", 1024, echo);
+
+    let comment = post_body(&router, "/comment");
+    let avatar = post_body(&router, "/avatar");
+    let upload = post_body(&router, "/upload?name=a.txt");
+
+    assert!(comment.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+    assert!(avatar.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+    assert!(avatar.contains("Connection:close\r\n"));
+    assert!(upload.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(upload.ends_with(&"a".repeat(100)));
+  }
+
+  #[test]
+  fn test_default_headers_on_rejected_request() {
+    let default_headers = vec![("X-Content-Type-Options".to_string(), "nosniff".to_string())];
+    let router = Router::new(ServerConfig { max_body_size: 64, default_headers, ..ServerConfig::default() });
+
+    let response = post_body(&router, "/comment");
+
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+    assert!(response.contains("X-Content-Type-Options:nosniff\r\n"));
+  }
+
+  #[test]
+  fn test_large_body_spilled_to_temporary_file() {
+    let mut router = Router::new(ServerConfig { body_spill_threshold: Some(64), ..ServerConfig::default() });
+    let spilled: Arc<Mutex<Option<PathBuf>>> = Arc::new(Mutex::new(None));
+    let seen = Arc::clone(&spilled);
+    router.add_route(Method::POST, "/upload", move |request| {
+      let path = request.body_file.clone().unwrap();
+      assert!(path.exists());
+      *seen.lock().unwrap() = Some(path);
+
+      let mut body = Vec::new();
+      request.body_reader().unwrap().read_to_end(&mut body).unwrap();
+      assert!(request.body_bytes().is_empty());
+      HttpResponse::with_bytes("200", None, Some(body))
+    });
+
+    let response = post_body(&router, "/upload");
+    let path = spilled.lock().unwrap().clone().unwrap();
+
+    assert!(response.ends_with(&"a".repeat(100)));
+    assert!(!path.exists());
+  }
+
+  #[test]
+  fn test_headers_without_blank_line_time_out() {
+    let mut client = connect(ServerConfig { read_timeout: Duration::from_millis(100), ..ServerConfig::default() });
+    client.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n").unwrap();
+
+    let started = Instant::now();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+    assert!(response.contains("Connection:close\r\n"));
+    assert!(started.elapsed() < Duration::from_secs(2));
+  }
+
+  #[test]
+  fn test_headers_without_blank_line_closed() {
+    let mut client = connect(ServerConfig::default());
+    client.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n").unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+  }
+
+  #[test]
+  fn test_short_body_rejected() {
+    let mut client = connect(ServerConfig::default());
+    client.write_all(b"POST /api/shipping/orders HTTP/1.1\r\nHost: localhost\r\nContent-Length: 100\r\n\r\n{\"order_id\"").unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(response.contains("Connection:close\r\n"));
+  }
+
+  #[test]
+  fn test_read_request_reuses_buffer() {
+    let raw = "GET /first HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\nGET /second HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let mut reader = io::Cursor::new(raw.as_bytes());
+    let mut buffer: Vec<u8> = Vec::new();
+
+    let first: HttpRequest = read_text(&mut reader, &mut buffer).unwrap().unwrap().into();
+    let allocation = buffer.as_ptr();
+    let second: HttpRequest = read_text(&mut reader, &mut buffer).unwrap().unwrap().into();
+
+    assert_eq!(first.resource, Resource::Path("/first".to_string()));
+    assert_eq!(second.resource, Resource::Path("/second".to_string()));
+    assert_eq!(second.headers.len(), 1);
+    assert_eq!(buffer.as_ptr(), allocation);
+  }
+
+  #[test]
+  fn test_read_buffer_capacity_configured() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    assert_eq!(buffered_reader(&client, 1024).unwrap().capacity(), 1024);
+    assert_eq!(buffered_reader(&client, ServerConfig::default().read_buffer_capacity).unwrap().capacity(), 8192);
+
+    // A buffer smaller than the request still reads it whole
+    let mut client = connect(ServerConfig {
+      read_buffer_capacity: 16,
+      ..ServerConfig::default()
+    });
+    let mut reader = BufReader::new(client.try_clone().unwrap());
+    let mut buffer: Vec<u8> = Vec::new();
+    client
+      .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nAccept: text/html, application/xhtml+xml\r\n\r\n")
+      .unwrap();
+    assert!(read_text(&mut reader, &mut buffer).unwrap().unwrap().starts_with("HTTP/1.1 200 OK\r\n"));
+  }
+
+  #[test]
+  fn test_pipelined_requests_framed_by_body() {
+    let mut client = connect(ServerConfig::default());
+    let mut reader = BufReader::new(client.try_clone().unwrap());
+    let mut buffer: Vec<u8> = Vec::new();
+
+    client
+      .write_all(b"POST /api/shipping/orders HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\n\r\noopsGET /health HTTP/1.1\r\nHost: localhost\r\n\r\n")
+      .unwrap();
+    let first = read_text(&mut reader, &mut buffer).unwrap().unwrap().to_string();
+    let second = read_text(&mut reader, &mut buffer).unwrap().unwrap().to_string();
+
+    assert!(first.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(first.contains("\"error\":\"invalid_json\""));
+    assert!(second.starts_with("HTTP/1.1 200 OK\r\n"));
+  }
+
+  #[test]
+  fn test_pipelined_requests_responded_in_order() {
+    let mut client = connect(ServerConfig::default());
+    let mut reader = BufReader::new(client.try_clone().unwrap());
+    let mut buffer: Vec<u8> = Vec::new();
+
+    let requests: String = ["/health", "/robots.txt", "/missing"]
+      .iter()
+      .enumerate()
+      .map(|(i, path)| format!("GET {} HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: pipe-{}\r\n\r\n", path, i))
+      .collect();
+    client.write_all(requests.as_bytes()).unwrap();
+
+    let mut responses: Vec<String> = Vec::new();
+    for _ in 0..3 {
+      responses.push(read_text(&mut reader, &mut buffer).unwrap().unwrap().to_string());
+    }
+
+    for (i, response) in responses.iter().enumerate() {
+      assert!(response.contains(&format!("X-Request-Id:pipe-{}\r\n", i)));
+    }
+    assert!(responses[0].starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(responses[1].ends_with("User-agent: *\nAllow: /\n"));
+    assert!(responses[2].starts_with("HTTP/1.1 404 Not Found\r\n"));
+  }
+
+  #[test]
+  fn test_trailing_data_ignored_after_last_response() {
+    let mut client = connect(ServerConfig::default());
+    let mut reader = BufReader::new(client.try_clone().unwrap());
+    let mut buffer: Vec<u8> = Vec::new();
+
+    let mut raw = b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n".to_vec();
+    raw.extend(std::iter::repeat_n(b'x', 16 * 1024));
+    client.write_all(&raw).unwrap();
+
+    let response = read_text(&mut reader, &mut buffer).unwrap().unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert_eq!(read_text(&mut reader, &mut buffer).unwrap(), None);
+  }
+
+  #[test]
+  fn test_connection_closed_after_max_requests() {
+    let config = ServerConfig {
+      keep_alive_max_requests: 2,
+      ..ServerConfig::default()
+    };
+    let mut client = connect(config);
+    let mut reader = BufReader::new(client.try_clone().unwrap());
+    let mut buffer: Vec<u8> = Vec::new();
+
+    client.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let first = read_text(&mut reader, &mut buffer).unwrap().unwrap();
+    assert!(!first.contains("Connection:close"));
+
+    client.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let second = read_text(&mut reader, &mut buffer).unwrap().unwrap();
+    assert!(second.contains("Connection:close\r\n"));
+
+    // The server closed the connection after the second response
+    assert_eq!(read_text(&mut reader, &mut buffer).unwrap(), None);
+  }
+
+  #[test]
+  fn test_connection_closed_on_request() {
+    let mut client = connect(ServerConfig::default());
+    let mut reader = BufReader::new(client.try_clone().unwrap());
+    let mut buffer: Vec<u8> = Vec::new();
+
+    client.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: Close\r\n\r\n").unwrap();
+    let response = read_text(&mut reader, &mut buffer).unwrap().unwrap();
+    assert!(response.contains("Connection:close\r\n"));
+
+    assert_eq!(read_text(&mut reader, &mut buffer).unwrap(), None);
+  }
+
+  #[test]
+  fn test_http_0_9_simple_response() {
+    let mut client = connect(ServerConfig {
+      allow_http_0_9: true,
+      ..ServerConfig::default()
+    });
+
+    client.write_all(b"GET /robots.txt\r\n\r\n").unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+
+    assert_eq!(response, "User-agent: *\nAllow: /\n");
+  }
+
+  #[test]
+  fn test_keep_alive_header_reflects_config() {
+    let mut client = connect(ServerConfig {
+      keep_alive_max_requests: 3,
+      keep_alive_timeout: Duration::from_secs(7),
+      ..ServerConfig::default()
+    });
+    let mut reader = BufReader::new(client.try_clone().unwrap());
+    let mut buffer: Vec<u8> = Vec::new();
+
+    client.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let first = read_text(&mut reader, &mut buffer).unwrap().unwrap();
+    assert!(first.contains("Keep-Alive:timeout=7, max=2\r\n"));
+
+    client.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let second = read_text(&mut reader, &mut buffer).unwrap().unwrap();
+    assert!(second.contains("Keep-Alive:timeout=7, max=1\r\n"));
+
+    client.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let last = read_text(&mut reader, &mut buffer).unwrap().unwrap();
+    assert!(last.contains("Connection:close\r\n"));
+    assert!(!last.contains("Keep-Alive"));
+  }
+
+  #[test]
+  fn test_http_1_0_closed_by_default() {
+    let mut client = connect(ServerConfig::default());
+    let mut reader = BufReader::new(client.try_clone().unwrap());
+    let mut buffer: Vec<u8> = Vec::new();
+
+    client.write_all(b"GET /health HTTP/1.0\r\n\r\n").unwrap();
+    let response = read_text(&mut reader, &mut buffer).unwrap().unwrap();
+    assert!(response.contains("Connection:close\r\n"));
+
+    assert_eq!(read_text(&mut reader, &mut buffer).unwrap(), None);
+  }
+
+  #[test]
+  fn test_http_1_0_kept_alive_on_request() {
+    let mut client = connect(ServerConfig::default());
+    let mut reader = BufReader::new(client.try_clone().unwrap());
+    let mut buffer: Vec<u8> = Vec::new();
+
+    client.write_all(b"GET /health HTTP/1.0\r\nConnection: keep-alive\r\n\r\n").unwrap();
+    let first = read_text(&mut reader, &mut buffer).unwrap().unwrap();
+    assert!(first.contains("Connection:keep-alive\r\n"));
+
+    client.write_all(b"GET /health HTTP/1.0\r\n\r\n").unwrap();
+    let second = read_text(&mut reader, &mut buffer).unwrap().unwrap();
+    assert!(second.contains("Connection:close\r\n"));
+  }
+
+  #[test]
+  fn test_invalid_utf8_bad_request() {
+    let mut client = connect(ServerConfig::default());
+    let mut reader = BufReader::new(client.try_clone().unwrap());
+    let mut buffer: Vec<u8> = Vec::new();
+
+    client.write_all(b"GET /caf\xe9 HTTP/1.1\r\nHost: localhost\r\nX-Name: \xff\r\n\r\n").unwrap();
+    let response = read_text(&mut reader, &mut buffer).unwrap().unwrap();
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(response.contains("Connection:close\r\n"));
+
+    assert_eq!(read_text(&mut reader, &mut buffer).unwrap(), None);
+  }
+
+  #[test]
+  fn test_binary_body_received_intact() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let mut router = Router::new(ServerConfig::default());
+    router.add_route(Method::POST, "/upload", |request| {
+      HttpResponse::with_bytes("200", None, Some(request.body_bytes().to_vec()))
+    });
+    let body: Vec<u8> = (0..=255).rev().collect();
+
+    let client = thread::spawn(move || {
+      let mut client = TcpStream::connect(address).unwrap();
+      let mut raw = b"POST /upload HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 256\r\n\r\n".to_vec();
+      raw.extend((0..=255).rev());
+      client.write_all(&raw).unwrap();
+      let mut response = Vec::new();
+      client.read_to_end(&mut response).unwrap();
+      response
+    });
+
+    let (stream, _) = listener.accept().unwrap();
+    serve(stream, &router).unwrap();
+    let response = client.join().unwrap();
+
+    assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with(&body));
+  }
+
+  /// Serves a single request over a connection and returns the client address it was given.
+  fn client_ip(config: ServerConfig, raw: &str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let mut router = Router::new(config);
+    router.add_route(Method::GET, "/ip", |request| {
+      let ip = request.client_ip().map(|ip| ip.to_string());
+      HttpResponse::new("200", None, Some(format!("{} {}", ip.unwrap_or_default(), request.scheme())))
+    });
+
+    client.write_all(raw.as_bytes()).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    serve(stream, &router).unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    response.split("\r\n\r\n").nth(1).unwrap().to_string()
+  }
+
+  #[test]
+  fn test_forwarded_headers_trusted_only_when_enabled() {
+    let raw = "GET /ip HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+               X-Forwarded-For: 203.0.113.7\r\nX-Forwarded-Proto: https\r\n\r\n";
+    let trusted = ServerConfig { trust_proxy: true, ..ServerConfig::default() };
+
+    assert_eq!(client_ip(ServerConfig::default(), raw), "127.0.0.1 http");
+    assert_eq!(client_ip(trusted, raw), "203.0.113.7 https");
+  }
+
+  #[test]
+  fn test_streamed_body_buffered_for_http_1_0() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let mut router = Router::new(ServerConfig::default());
+    router.add_route(Method::GET, "/stream", |_request| {
+      HttpResponse::from_reader("200", None, io::Cursor::new(b"streamed".to_vec()))
+    });
+
+    client.write_all(b"GET /stream HTTP/1.0\r\n\r\n").unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    serve(stream, &router).unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+
+    assert!(response.contains("Content-Length: 8\r\n"));
+    assert!(response.contains("Connection:close\r\n"));
+    assert!(!response.contains("Transfer-Encoding"));
+    assert!(response.ends_with("\r\n\r\nstreamed"));
+  }
+
+  #[test]
+  fn test_get_body_does_not_corrupt_next_request() {
+    let mut client = connect(ServerConfig::default());
+    let mut reader = BufReader::new(client.try_clone().unwrap());
+    let mut buffer: Vec<u8> = Vec::new();
+
+    client
+      .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\n\r\nGET GET /nothing HTTP/1.1\r\nHost: localhost\r\n\r\n")
+      .unwrap();
+    let first = read_text(&mut reader, &mut buffer).unwrap().unwrap().to_string();
+    let second = read_text(&mut reader, &mut buffer).unwrap().unwrap().to_string();
+
+    assert!(first.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(second.starts_with("HTTP/1.1 404 Not Found\r\n"));
+  }
+
+  #[test]
+  fn test_invalid_content_length_rejected() {
+    for length in ["5, 7", "-1", "abc"] {
+      let mut client = connect(ServerConfig::default());
+      let mut reader = BufReader::new(client.try_clone().unwrap());
+      let raw = format!("GET /health HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n", length);
+
+      client.write_all(raw.as_bytes()).unwrap();
+      let response = read_text(&mut reader, &mut Vec::new()).unwrap().unwrap().to_string();
+
+      assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+      assert!(response.contains("Connection:close\r\n"));
+    }
+
+    let raw = "POST /x HTTP/1.1\r\nContent-Length: 1\r\ncontent-length: 2\r\n\r\nab";
+    let error = read_text(&mut io::Cursor::new(raw.as_bytes()), &mut Vec::new()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+  }
+
+  #[test]
+  fn test_chunked_body_not_read_as_next_request() {
+    let mut client = connect(ServerConfig::default());
+    let raw = "POST /api/shipping/orders HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n\
+               2a\r\nGET /health HTTP/1.1\r\nHost: localhost\r\n\r\n\r\n0\r\n\r\n\
+               GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+    client.write_all(raw.as_bytes()).unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(response.contains("Connection:close\r\n"));
+    assert_eq!(response.matches("HTTP/1.1 ").count(), 1);
+  }
+
+  #[test]
+  fn test_header_section_limited() {
+    let raw = format!("GET / HTTP/1.1\r\nX-Padding: {}\r\n\r\n", "a".repeat(MAX_HEAD_LENGTH));
+    let error = read_request(&mut io::Cursor::new(raw.as_bytes()), &mut Vec::new()).unwrap_err();
+
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(error.to_string(), "the header section is over the limit of 65536 bytes");
+  }
+
+  #[test]
+  fn test_request_logged_at_expected_levels() {
+    logging::tests::capture();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let client = thread::spawn(move || {
+      let mut client = TcpStream::connect(address).unwrap();
+      client.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: log-1\r\n\r\n").unwrap();
+      client.shutdown(std::net::Shutdown::Write).unwrap();
+      let mut reader = BufReader::new(client);
+      read_text(&mut reader, &mut Vec::new()).unwrap().map(str::to_string)
+    });
+
+    let (stream, _) = listener.accept().unwrap();
+    serve(stream, &Router::new(ServerConfig::default())).unwrap();
+    assert!(client.join().unwrap().is_some());
+
+    let events = logging::tests::captured();
+    assert!(events.contains(&(Level::Debug, "Routing GET /health to the static page handler".to_string())));
+    assert!(events.contains(&(Level::Info, "[log-1] 127.0.0.1 GET /health 200".to_string())));
+    assert!(events.iter().all(|(level, _)| *level != Level::Warn && *level != Level::Error));
+  }
+
+  #[test]
+  fn test_slow_request_closed_after_timeout() {
+    logging::tests::capture();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let mut router = Router::new(ServerConfig {
+      request_timeout: Some(Duration::from_millis(50)),
+      ..ServerConfig::default()
+    });
+    router.add_route(Method::GET, "/slow", |_request| {
+      thread::sleep(Duration::from_millis(150));
+      HttpResponse::new("200", None, Some("late".into()))
+    });
+
+    let client = thread::spawn(move || {
+      let mut client = TcpStream::connect(address).unwrap();
+      let mut reader = BufReader::new(client.try_clone().unwrap());
+      let mut buffer: Vec<u8> = Vec::new();
+      client.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+      let fast = read_text(&mut reader, &mut buffer).unwrap().map(str::to_string);
+      client.write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: slow-1\r\n\r\n").unwrap();
+      let slow = read_text(&mut reader, &mut buffer).ok().flatten().map(str::to_string);
+      (fast, slow)
+    });
+
+    let (stream, _) = listener.accept().unwrap();
+    serve(stream, &router).unwrap();
+    let (fast, slow) = client.join().unwrap();
+
+    assert!(fast.unwrap().starts_with("HTTP/1.1 200 OK\r\n"));
+    assert_eq!(slow, None);
+    let events = logging::tests::captured();
+    assert!(events
+      .iter()
+      .any(|(level, message)| *level == Level::Warn && message.starts_with("[slow-1] GET /slow took ")));
+  }
+
+  #[test]
+  fn test_connection_closed_after_idle_timeout() {
+    let config = ServerConfig {
+      keep_alive_timeout: Duration::from_millis(100),
+      ..ServerConfig::default()
+    };
+    let client = connect(config);
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut reader = BufReader::new(client);
+
+    let start = Instant::now();
+    assert_eq!(read_text(&mut reader, &mut Vec::new()).unwrap(), None);
+    assert!(start.elapsed() < Duration::from_secs(5));
+  }
+}
@@ -0,0 +1,147 @@
+use std::{collections::HashMap, time::Duration};
+
+use http::{
+  httprequest::{HttpRequest, Method},
+  httpresponse::HttpResponse,
+};
+
+/// Represents the cross-origin resource sharing (CORS) policy of a route, telling browsers
+/// which other origins may call it.
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+  /// Origins allowed to call the route (e.g. `https://example.com`), `*` allowing any.
+  pub allowed_origins: Vec<String>,
+  /// Methods cross-origin requests may use.
+  pub allowed_methods: Vec<Method>,
+  /// Request headers cross-origin requests may send, besides the always allowed ones.
+  pub allowed_headers: Vec<String>,
+  /// Time browsers may cache a preflight response for. Not announced by default.
+  pub max_age: Option<Duration>,
+}
+
+impl Default for CorsPolicy {
+  fn default() -> Self {
+    Self {
+      allowed_origins: vec!["*".to_string()],
+      allowed_methods: vec![Method::GET, Method::HEAD, Method::POST],
+      allowed_headers: Vec::new(),
+      max_age: None,
+    }
+  }
+}
+
+impl CorsPolicy {
+  /// Gets the value of the `Access-Control-Allow-Origin` header for the origin of the
+  /// given request, if it is allowed.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: Cross-origin HTTP request.
+  fn allowed_origin<'r>(&self, request: &'r HttpRequest) -> Option<&'r str> {
+    let origin = request.header("Origin")?;
+    self
+      .allowed_origins
+      .iter()
+      .find(|allowed| *allowed == "*" || allowed.eq_ignore_ascii_case(origin))
+      .map(|allowed| if allowed == "*" { "*" } else { origin })
+  }
+
+  /// Checks whether the given request is a preflight, i.e. an `OPTIONS` request asking
+  /// whether a cross-origin request may be sent.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request to check.
+  pub fn is_preflight(request: &HttpRequest) -> bool {
+    request.method == Method::OPTIONS
+      && request.header("Origin").is_some()
+      && request.header("Access-Control-Request-Method").is_some()
+  }
+
+  /// Produces the response to the given preflight request.
+  ///
+  /// The response has no CORS headers if the origin or the method is not allowed, so the
+  /// browser does not send the actual request.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: Preflight HTTP request.
+  pub fn preflight<'a>(&self, request: &HttpRequest) -> HttpResponse<'a> {
+    let mut response = HttpResponse::new("204", Some(HashMap::new()), None);
+    response.set_header("Vary", "Origin");
+
+    let method = request.header("Access-Control-Request-Method").map(|m| Method::from(m.trim()));
+    let (Some(origin), Some(method)) = (self.allowed_origin(request), method) else {
+      return response;
+    };
+    if !self.allowed_methods.contains(&method) {
+      return response;
+    }
+
+    let methods: Vec<String> = self.allowed_methods.iter().map(|m| format!("{:?}", m)).collect();
+    response.set_header("Access-Control-Allow-Origin", origin);
+    response.set_header("Access-Control-Allow-Methods", &methods.join(", "));
+    if !self.allowed_headers.is_empty() {
+      response.set_header("Access-Control-Allow-Headers", &self.allowed_headers.join(", "));
+    }
+    if let Some(max_age) = self.max_age {
+      response.set_header("Access-Control-Max-Age", &max_age.as_secs().to_string());
+    }
+    response
+  }
+
+  /// Adds the CORS headers to the response of an actual cross-origin request.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request responded to.
+  /// * `response`: HTTP response to the request.
+  pub fn apply(&self, request: &HttpRequest, response: &mut HttpResponse) {
+    if let Some(origin) = self.allowed_origin(request) {
+      response.set_header("Access-Control-Allow-Origin", origin);
+      response.set_header("Vary", "Origin");
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_preflight_allowed() {
+    let policy = CorsPolicy {
+      allowed_origins: vec!["https://example.com".to_string()],
+      allowed_headers: vec!["Content-Type".to_string()],
+      max_age: Some(Duration::from_secs(600)),
+      ..CorsPolicy::default()
+    };
+    let request: HttpRequest = "OPTIONS /api/ping HTTP/1.1\r\nHost: localhost\r\nOrigin: https://example.com\r\n\
+                                Access-Control-Request-Method: POST\r\n\r\n"
+      .into();
+
+    let response = policy.preflight(&request);
+
+    assert!(CorsPolicy::is_preflight(&request));
+    assert_eq!(response.status_code(), "204");
+    assert_eq!(response.header("Access-Control-Allow-Origin"), Some("https://example.com"));
+    assert_eq!(response.header("Access-Control-Allow-Methods"), Some("GET, HEAD, POST"));
+    assert_eq!(response.header("Access-Control-Allow-Headers"), Some("Content-Type"));
+    assert_eq!(response.header("Access-Control-Max-Age"), Some("600"));
+  }
+
+  #[test]
+  fn test_preflight_refused() {
+    let policy = CorsPolicy {
+      allowed_origins: vec!["https://example.com".to_string()],
+      ..CorsPolicy::default()
+    };
+    for raw in [
+      "OPTIONS /api/ping HTTP/1.1\r\nOrigin: https://evil.com\r\nAccess-Control-Request-Method: GET\r\n\r\n",
+      "OPTIONS /api/ping HTTP/1.1\r\nOrigin: https://example.com\r\nAccess-Control-Request-Method: DELETE\r\n\r\n",
+    ] {
+      let response = policy.preflight(&raw.into());
+      assert_eq!(response.header("Access-Control-Allow-Origin"), None, "{}", raw);
+    }
+  }
+}
@@ -0,0 +1,163 @@
+use http::{httprequest::HttpRequest, httpresponse::HttpResponse};
+use log::{debug};
+
+use crate::session::{SessionData, SessionStore};
+
+/// Name of the session value holding the token, and of the form field sending it back.
+pub const CSRF_FIELD: &str = "csrf_token";
+
+/// Name of the header sending the token back, for scripts that post without a form.
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// Gets the token of the given session, issuing one on first use, to embed in the forms
+/// the session posts.
+///
+/// # Arguments
+///
+/// * `session`: Values of the session of the client.
+pub fn token(session: &mut SessionData) -> String {
+  session.entry(CSRF_FIELD.to_string()).or_insert_with(SessionStore::new_id).clone()
+}
+
+/// Checks whether the given request sends back the token of its session, in the
+/// `X-CSRF-Token` header or the `csrf_token` field of its form.
+///
+/// # Arguments
+///
+/// * `request`: HTTP request to verify.
+/// * `session`: Values of the session of the client.
+pub fn verify(request: &HttpRequest, session: &SessionData) -> bool {
+  let Some(expected) = session.get(CSRF_FIELD) else {
+    return false;
+  };
+  let sent = request.header(CSRF_HEADER).or_else(|| {
+    request
+      .body_str()?
+      .split('&')
+      .filter_map(|pair| pair.split_once('='))
+      .find(|(key, _)| *key == CSRF_FIELD)
+      .map(|(_, value)| value)
+  });
+
+  // Compare every byte, so the time taken does not tell how much of the token matched
+  sent.is_some_and(|sent| {
+    sent.len() == expected.len() && sent.bytes().zip(expected.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+  })
+}
+
+/// Represents a guard rejecting the unsafe requests (e.g. `POST`) to the protected paths
+/// that do not send back the token of their session.
+#[derive(Clone)]
+pub struct CsrfGuard {
+  /// Store of the sessions the tokens are tied to.
+  store: SessionStore,
+  /// Path prefixes whose unsafe requests are verified.
+  paths: Vec<String>,
+}
+
+impl CsrfGuard {
+  /// Creates a new [`CsrfGuard`] object.
+  ///
+  /// # Arguments
+  ///
+  /// * `store`: Store of the sessions the tokens are tied to.
+  /// * `paths`: Path prefixes whose unsafe requests are verified (e.g. `/account`).
+  pub fn new(store: SessionStore, paths: &[&str]) -> Self {
+    Self {
+      store,
+      paths: paths.iter().map(|p| p.to_string()).collect(),
+    }
+  }
+
+  /// Checks whether the given request must send back the token of its session.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request to check.
+  fn is_protected(&self, request: &HttpRequest) -> bool {
+    !request.method.is_safe() && self.paths.iter().any(|prefix| request.path().starts_with(prefix.as_str()))
+  }
+
+  /// Wraps a handler with access to the session of the client, answering `403` instead
+  /// of calling it when the request must send a valid token and does not.
+  ///
+  /// # Arguments
+  ///
+  /// * `handler`: Function producing the response from the request and its session.
+  pub fn handler<F>(&self, handler: F) -> impl Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static
+  where
+    F: Fn(&HttpRequest, &mut SessionData) -> HttpResponse<'static> + Send + Sync + 'static,
+  {
+    let guard = self.clone();
+    self.store.handler(move |request, session| {
+      if guard.is_protected(request) && !verify(request, session) {
+        debug!("Rejecting {:?} {} without a valid CSRF token", request.method, request.path());
+        return HttpResponse::new("403", None, None);
+      }
+      handler(request, session)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::Duration;
+
+  /// Wraps a handler serving a form on GET and accepting it on POST.
+  fn form(guard: &CsrfGuard) -> impl Fn(&HttpRequest) -> HttpResponse<'static> {
+    guard.handler(|request, session| match request.method.is_safe() {
+      true => HttpResponse::new("200", None, Some(token(session))),
+      false => HttpResponse::new("200", None, Some("saved".to_string())),
+    })
+  }
+
+  /// Gets a session and its token from the form handler.
+  fn session_and_token(handler: &impl Fn(&HttpRequest) -> HttpResponse<'static>) -> (String, String) {
+    let response = handler(&"GET /account HTTP/1.1\r\nHost: localhost\r\n\r\n".into());
+    let cookie = response.header("Set-Cookie").unwrap();
+    let (pair, _) = cookie.split_once(';').unwrap();
+    (pair.to_string(), response.body().to_string())
+  }
+
+  #[test]
+  fn test_valid_token_accepted() {
+    let handler = form(&CsrfGuard::new(SessionStore::new(Duration::from_secs(60)), &["/account"]));
+    let (cookie, token) = session_and_token(&handler);
+
+    let body = format!("name=x&csrf_token={}", token);
+    let raw = format!(
+      "POST /account HTTP/1.1\r\nHost: localhost\r\nCookie: {}\r\nContent-Length: {}\r\n\r\n{}",
+      cookie,
+      body.len(),
+      body
+    );
+    let by_header = format!("POST /account HTTP/1.1\r\nHost: localhost\r\nCookie: {}\r\nX-CSRF-Token: {}\r\n\r\n", cookie, token);
+
+    assert_eq!(handler(&raw.as_str().into()).body(), "saved");
+    assert_eq!(handler(&by_header.as_str().into()).body(), "saved");
+  }
+
+  #[test]
+  fn test_missing_or_wrong_token_forbidden() {
+    let handler = form(&CsrfGuard::new(SessionStore::new(Duration::from_secs(60)), &["/account"]));
+    let (cookie, token) = session_and_token(&handler);
+
+    let missing = format!("POST /account HTTP/1.1\r\nHost: localhost\r\nCookie: {}\r\n\r\n", cookie);
+    let wrong = format!("POST /account HTTP/1.1\r\nHost: localhost\r\nCookie: {}\r\nX-CSRF-Token: {}0\r\n\r\n", cookie, token);
+    let other_session = format!("POST /account HTTP/1.1\r\nHost: localhost\r\nX-CSRF-Token: {}\r\n\r\n", token);
+
+    for raw in [missing, wrong, other_session] {
+      assert_eq!(handler(&raw.as_str().into()).status_code(), "403", "{}", raw);
+    }
+  }
+
+  #[test]
+  fn test_unprotected_path_not_verified() {
+    let handler = form(&CsrfGuard::new(SessionStore::new(Duration::from_secs(60)), &["/account"]));
+
+    let response = handler(&"POST /comments HTTP/1.1\r\nHost: localhost\r\n\r\n".into());
+
+    assert_eq!(response.body(), "saved");
+  }
+}
@@ -1,11 +1,26 @@
-use std::{collections::HashMap, env, fs, path::Path};
+use std::{
+  collections::{hash_map::DefaultHasher, HashMap},
+  hash::{Hash, Hasher},
+  env, fs,
+  io::{self, Read},
+  path::Path,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use http::{
-  httprequest::{HttpRequest, Resource},
+  date,
+  httprequest::{HttpRequest, Method, Resource},
   httpresponse::HttpResponse,
 };
+use log::{debug, error};
 use serde::{Deserialize, Serialize};
 
+use crate::{
+  compress, mime,
+  template::{self, Unmatched},
+};
+
 /// Represents a handler for HTTP requests.
 pub trait Handler {
   /// Handles the given request to produce the respective response.
@@ -13,7 +28,7 @@ pub trait Handler {
   /// # Arguments
   ///
   /// * `request`: HTTP request to handle.
-  fn handle(request: &HttpRequest) -> HttpResponse;
+  fn handle(&self, request: &HttpRequest) -> HttpResponse<'_>;
 
   /// Loads the contents of the specified file from the server public directory.
   ///
@@ -29,10 +44,48 @@ pub trait Handler {
 
     contents.ok()
   }
+
+  /// Loads the specified template from the server public directory and substitutes its
+  /// `{{name}}` placeholders with the HTML-escaped variables, keeping unmatched ones as-is.
+  ///
+  /// # Arguments
+  ///
+  /// * `file_name`: Name of the template to load relative to the public directory.
+  /// * `vars`: Values of the variables, by name.
+  fn render_template(file_name: &str, vars: &HashMap<&str, &str>) -> Option<String> {
+    Self::render_template_with(file_name, vars, Unmatched::Keep)
+  }
+
+  /// Loads the specified template from the server public directory and substitutes its
+  /// `{{name}}` placeholders with the HTML-escaped variables.
+  ///
+  /// # Arguments
+  ///
+  /// * `file_name`: Name of the template to load relative to the public directory.
+  /// * `vars`: Values of the variables, by name.
+  /// * `unmatched`: What becomes of the placeholders with no matching variable.
+  fn render_template_with(
+    file_name: &str,
+    vars: &HashMap<&str, &str>,
+    unmatched: Unmatched,
+  ) -> Option<String> {
+    Self::load_file(file_name).map(|contents| template::render(&contents, vars, unmatched))
+  }
+
+  /// Produces a "404 Not Found" response whose page echoes the (escaped) requested path.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request that was not found.
+  fn not_found(request: &HttpRequest) -> HttpResponse<'static> {
+    let Resource::Path(p) = &request.resource;
+    let vars: HashMap<&str, &str> = HashMap::from([("path", p.as_str())]);
+    HttpResponse::new("404", None, Self::render_template("404.html", &vars))
+  }
 }
 
 /// Represents the status of shipping order.
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct OrderStatus {
   /// Unique identifier (ID) of the order.
   order_id: i32,
@@ -42,84 +95,1562 @@ pub struct OrderStatus {
   order_status: String,
 }
 
+/// Path of the shipping orders resource of the API.
+const ORDERS_PATH: &str = "/api/shipping/orders";
+
+/// Path of the export of every shipping order as newline-delimited JSON.
+const EXPORT_PATH: &str = "/api/shipping/export";
+
+/// Fields of a shipping order that can be changed by a merge patch.
+const PATCHABLE_FIELDS: [&str; 2] = ["order_date", "order_status"];
+
+/// Maximum number of idempotency keys remembered, the oldest being forgotten first.
+const IDEMPOTENCY_MAX_KEYS: usize = 1024;
+
+/// Time the response to a request with an idempotency key is replayed for.
+const IDEMPOTENCY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Statuses a shipping order may be in.
+const ORDER_STATUSES: [&str; 4] = ["Pending", "Shipped", "Delivered", "Cancelled"];
+
+impl OrderStatus {
+  /// Checks that every field of the order holds an acceptable value.
+  ///
+  /// Returns a description of the first invalid field, if any.
+  pub fn validate(&self) -> Result<(), String> {
+    if self.order_id <= 0 {
+      return Err(format!("order_id must be positive, got {}", self.order_id));
+    }
+    if self.order_date.trim().is_empty() {
+      return Err("order_date must not be blank".to_string());
+    }
+    if !ORDER_STATUSES.contains(&self.order_status.as_str()) {
+      return Err(format!(
+        "order_status must be one of {}, got {:?}",
+        ORDER_STATUSES.join(", "),
+        self.order_status
+      ));
+    }
+    Ok(())
+  }
+
+  /// Gets the entity tag of the order, a quoted hash of its JSON representation.
+  pub fn etag(&self) -> String {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(self).unwrap().hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+  }
+}
+
+/// Represents the shipping orders as newline-delimited JSON, each one serialized only as
+/// the export is read so they are never all buffered.
+struct OrderLines {
+  /// Shipping orders to export.
+  orders: Arc<Mutex<Vec<OrderStatus>>>,
+  /// Index of the next order to serialize.
+  next: usize,
+  /// Serialized line of the current order.
+  line: Vec<u8>,
+  /// Number of bytes of the current line already read.
+  read: usize,
+}
+
+impl Read for OrderLines {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    while self.read == self.line.len() {
+      // Only lock the orders for one line, not for the whole export
+      let orders = self.orders.lock().unwrap_or_else(|e| e.into_inner());
+      let Some(order) = orders.get(self.next) else {
+        return Ok(0);
+      };
+      self.line = serde_json::to_vec(order)?;
+      self.line.push(b'\n');
+      self.read = 0;
+      self.next += 1;
+    }
+
+    let length = buf.len().min(self.line.len() - self.read);
+    buf[..length].copy_from_slice(&self.line[self.read..self.read + length]);
+    self.read += length;
+    Ok(length)
+  }
+}
+
 /// Represents a handler to serve the API (i.e. serve JSON files).
-pub struct WebServiceHandler;
+pub struct WebServiceHandler {
+  /// Shipping orders shared by every request, loaded once at startup.
+  orders: Arc<Mutex<Vec<OrderStatus>>>,
+  /// Whether every change to the orders is written back to the data file.
+  flush: bool,
+  /// Time the orders were loaded at, the last modification time of the unchanged ones.
+  loaded: SystemTime,
+  /// Time each order changed since it was loaded was last modified at, by identifier.
+  modified: Mutex<HashMap<i32, SystemTime>>,
+  /// Responses to the recent creations sent with an `Idempotency-Key` header, by key,
+  /// along with the time they were produced at.
+  idempotent: Mutex<HashMap<String, (Instant, HttpResponse<'static>)>>,
+}
 
 impl WebServiceHandler {
-  /// Loads shipping orders from a JSON data file.
-  fn load_json() -> Vec<OrderStatus> {
+  /// Creates a new [`WebServiceHandler`] object serving the given orders.
+  ///
+  /// # Arguments
+  ///
+  /// * `orders`: Shared in-memory store of shipping orders.
+  /// * `flush`: Whether to write the orders back to the data file on every change.
+  pub fn new(orders: Arc<Mutex<Vec<OrderStatus>>>, flush: bool) -> Self {
+    Self {
+      orders,
+      flush,
+      loaded: SystemTime::now(),
+      modified: Mutex::new(HashMap::new()),
+      idempotent: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Gets the time the given order was last modified at, to the second as HTTP-dates.
+  ///
+  /// # Arguments
+  ///
+  /// * `id`: Identifier of the order.
+  fn last_modified(&self, id: i32) -> SystemTime {
+    let time = self.modified.lock().unwrap().get(&id).copied().unwrap_or(self.loaded);
+    UNIX_EPOCH + Duration::from_secs(time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()))
+  }
+
+  /// Checks whether the given path (without query) is one of the API resources.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Path of the requested resource.
+  pub fn serves(&self, path: &str) -> bool {
+    !self.allowed_methods(path).is_empty()
+  }
+
+  /// Gets the methods the given path (without query) answers to, `HEAD` included like
+  /// `GET`, none if it is not one of the API resources.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Path of the requested resource.
+  pub fn allowed_methods(&self, path: &str) -> Vec<Method> {
+    if path.trim_end_matches('/') == EXPORT_PATH {
+      return vec![Method::GET, Method::HEAD];
+    }
+    match path.trim_end_matches('/').strip_prefix(ORDERS_PATH) {
+      Some("") => vec![Method::GET, Method::HEAD, Method::POST],
+      // A single order (/api/shipping/orders/{id})
+      Some(rest) if rest.strip_prefix('/').is_some_and(|id| !id.contains('/')) => {
+        vec![Method::GET, Method::HEAD, Method::PUT, Method::PATCH]
+      }
+      _ => Vec::new(),
+    }
+  }
+
+  /// Gets the full path of the JSON data file holding the shipping orders.
+  pub(crate) fn data_file() -> String {
     let default_path = format!("{}/data", env!("CARGO_MANIFEST_DIR"));
     let data_path = env::var("DATA_PATH").unwrap_or(default_path);
 
-    let full_path = format!("{}/{}", data_path, "orders.json");
+    format!("{}/{}", data_path, "orders.json")
+  }
+
+  /// Loads shipping orders from a JSON data file.
+  ///
+  /// A missing data file yields no orders, whereas a malformed one is a fatal error
+  /// so that flushing never overwrites data that could not be read.
+  pub fn load_json() -> Vec<OrderStatus> {
+    match fs::read_to_string(Self::data_file()) {
+      Ok(json_contents) => serde_json::from_str(json_contents.as_str())
+        .expect("the orders data file is not valid JSON"),
+      Err(_) => Vec::new(),
+    }
+  }
+
+  /// Writes the in-memory shipping orders back to the JSON data file.
+  pub fn flush(&self) -> std::io::Result<()> {
+    let orders = self.orders.lock().unwrap();
+    let json_contents = serde_json::to_string_pretty(&*orders)?;
 
-    let json_contents = fs::read_to_string(full_path).unwrap();
+    fs::write(Self::data_file(), json_contents)
+  }
+
+  /// Stores the order sent in the body of the request, unless a request with the same
+  /// `Idempotency-Key` header was served recently, whose response is replayed instead so
+  /// that retried creations are safe.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request holding the JSON order.
+  fn create_order_once(&self, request: &HttpRequest) -> HttpResponse<'static> {
+    let Some(key) = request.header("Idempotency-Key").filter(|key| !key.trim().is_empty()) else {
+      return self.create_order(request);
+    };
 
-    let orders: Vec<OrderStatus> = serde_json::from_str(&json_contents.as_str()).unwrap();
+    // Keep the lock while creating, so a concurrent retry waits for the first response
+    let mut idempotent = self.idempotent.lock().unwrap_or_else(|e| e.into_inner());
+    let now = Instant::now();
+    idempotent.retain(|_, (created, _)| now.duration_since(*created) < IDEMPOTENCY_TTL);
+    if let Some((_, response)) = idempotent.get(key.trim()) {
+      debug!("Replaying the response to the idempotency key {}", key.trim());
+      return response.clone();
+    }
 
-    orders
+    let response = self.create_order(request);
+    if idempotent.len() >= IDEMPOTENCY_MAX_KEYS {
+      let oldest = idempotent.iter().min_by_key(|(_, (created, _))| *created).map(|(key, _)| key.clone());
+      if let Some(oldest) = oldest {
+        idempotent.remove(&oldest);
+      }
+    }
+    idempotent.insert(key.trim().to_string(), (now, response.clone()));
+    response
+  }
+
+  /// Stores the order sent in the body of the request.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request holding the JSON order.
+  fn create_order(&self, request: &HttpRequest) -> HttpResponse<'static> {
+    let order: OrderStatus = match serde_json::from_slice(request.body_bytes()) {
+      Ok(order) => order,
+      Err(e) => return Self::error_response("400", "invalid_json", &e.to_string()),
+    };
+    if let Err(message) = order.validate() {
+      return Self::error_response("400", "invalid_order", &message);
+    }
+
+    let body = match serde_json::to_string(&order) {
+      Ok(body) => body,
+      Err(e) => return Self::serialization_failed(e),
+    };
+    let location = format!("{}/{}", ORDERS_PATH, order.order_id);
+    {
+      // Check and insert under the same lock, so two requests cannot create the same order
+      let mut orders = self.orders.lock().unwrap();
+      if orders.iter().any(|o| o.order_id == order.order_id) {
+        let message = format!("order {} already exists", order.order_id);
+        return Self::error_response("409", "duplicate_order", &message);
+      }
+      self.modified.lock().unwrap().insert(order.order_id, SystemTime::now());
+      orders.push(order);
+    }
+
+    if self.flush {
+      if let Err(e) = self.flush() {
+        error!("Failed to write the orders to the data file: {}", e);
+      }
+    }
+
+    let mut response = HttpResponse::created(&location, Some(body));
+    response.set_header("Content-Type", "application/json;charset=UTF-8");
+    response
+  }
+
+  /// Produces the response listing every order.
+  ///
+  /// # Arguments
+  ///
+  /// * `pretty`: Whether the JSON is indented.
+  fn list_orders(&self, pretty: bool) -> HttpResponse<'_> {
+    Self::json_response(&*self.orders.lock().unwrap(), pretty)
+  }
+
+  /// Produces the response streaming every order as newline-delimited JSON, one order
+  /// per line, for exports too large to be sent as a single array.
+  fn export_orders(&self) -> HttpResponse<'_> {
+    let lines = OrderLines {
+      orders: Arc::clone(&self.orders),
+      next: 0,
+      line: Vec::new(),
+      read: 0,
+    };
+    let mut response = HttpResponse::from_reader("200", None, lines);
+    response.set_header("Content-Type", "application/x-ndjson");
+    response
+  }
+
+  /// Produces the response serving the order with the given identifier.
+  ///
+  /// # Arguments
+  ///
+  /// * `id`: Identifier of the order, from the request path.
+  /// * `pretty`: Whether the JSON is indented.
+  fn get_order(&self, id: &str, pretty: bool) -> HttpResponse<'_> {
+    let orders = self.orders.lock().unwrap();
+    match orders.iter().find(|order| id.parse() == Ok(order.order_id)) {
+      Some(order) => Self::order_response(order, self.last_modified(order.order_id), pretty),
+      None => Self::error_response("404", "not_found", &format!("order {} does not exist", id)),
+    }
+  }
+
+  /// Partially updates the order with the given identifier, merging the JSON object sent
+  /// in the body of the request into it (JSON merge patch).
+  ///
+  /// Only the date and status of an order can be changed: any other field is rejected.
+  ///
+  /// # Arguments
+  ///
+  /// * `id`: Identifier of the order, from the request path.
+  /// * `request`: HTTP request holding the JSON merge patch.
+  fn patch_order(&self, id: &str, request: &HttpRequest) -> HttpResponse<'_> {
+    let patch: serde_json::Map<String, serde_json::Value> = match serde_json::from_slice(request.body_bytes()) {
+      Ok(patch) => patch,
+      Err(e) => return Self::error_response("400", "invalid_json", &e.to_string()),
+    };
+    if let Some(field) = patch.keys().find(|field| !PATCHABLE_FIELDS.contains(&field.as_str())) {
+      let message = format!("field {:?} cannot be patched, only {}", field, PATCHABLE_FIELDS.join(", "));
+      return Self::error_response("400", "unknown_field", &message);
+    }
+
+    self.update_order(id, request, |current| {
+      // Merge the patch into the current order, which is then checked as a whole
+      let mut merged = serde_json::to_value(current).map_err(Self::serialization_failed)?;
+      for (field, value) in patch {
+        merged[field] = value;
+      }
+      serde_json::from_value(merged).map_err(|e| Self::error_response("400", "invalid_order", &e.to_string()))
+    })
+  }
+
+  /// Replaces the order with the given identifier by the one sent in the body of the request.
+  ///
+  /// # Arguments
+  ///
+  /// * `id`: Identifier of the order, from the request path.
+  /// * `request`: HTTP request holding the JSON order.
+  fn replace_order(&self, id: &str, request: &HttpRequest) -> HttpResponse<'_> {
+    let order: OrderStatus = match serde_json::from_slice(request.body_bytes()) {
+      Ok(order) => order,
+      Err(e) => return Self::error_response("400", "invalid_json", &e.to_string()),
+    };
+
+    self.update_order(id, request, |current| {
+      if order.order_id == current.order_id {
+        Ok(order)
+      } else {
+        Err(Self::error_response("400", "invalid_order", "order_id must match the path"))
+      }
+    })
+  }
+
+  /// Updates the order with the given identifier, unless the request is conditioned by an
+  /// `If-Match` header that does not match the current entity tag of the order or,
+  /// without `If-Match`, by an `If-Unmodified-Since` date the order was modified after.
+  ///
+  /// # Arguments
+  ///
+  /// * `id`: Identifier of the order, from the request path.
+  /// * `request`: HTTP request updating the order.
+  /// * `update`: Function producing the updated order from the current one, or the error response.
+  fn update_order<F>(&self, id: &str, request: &HttpRequest, update: F) -> HttpResponse<'_>
+  where
+    F: FnOnce(&OrderStatus) -> Result<OrderStatus, HttpResponse<'static>>,
+  {
+    // Parse the precondition before taking the lock, an invalid date being ignored
+    let unmodified_since = request.header("If-Unmodified-Since").and_then(date::parse);
+    let mut orders = self.orders.lock().unwrap();
+    let Some(current) = orders.iter_mut().find(|order| id.parse() == Ok(order.order_id)) else {
+      return Self::error_response("404", "not_found", &format!("order {} does not exist", id));
+    };
+
+    // Refuse to overwrite a version of the order the client has not seen
+    if let Some(if_match) = request.header("If-Match") {
+      let etag = current.etag();
+      let matched = if_match.split(',').map(str::trim).any(|tag| tag == "*" || tag == etag);
+      if !matched {
+        let message = format!("order {} was modified, its entity tag is now {}", id, etag);
+        return Self::error_response("412", "precondition_failed", &message);
+      }
+    } else if let Some(since) = unmodified_since {
+      let modified = self.last_modified(current.order_id);
+      if modified > since {
+        let message = format!("order {} was modified on {}", id, date::format(modified));
+        return Self::error_response("412", "precondition_failed", &message);
+      }
+    }
+
+    let order = match update(current) {
+      Ok(order) => order,
+      Err(response) => return response,
+    };
+    if let Err(message) = order.validate() {
+      return Self::error_response("400", "invalid_order", &message);
+    }
+    *current = order.clone();
+    self.modified.lock().unwrap().insert(order.order_id, SystemTime::now());
+    drop(orders);
+
+    if self.flush {
+      if let Err(e) = self.flush() {
+        error!("Failed to write the orders to the data file: {}", e);
+      }
+    }
+
+    Self::order_response(&order, self.last_modified(order.order_id), Self::wants_pretty(request))
+  }
+
+  /// Produces a successful response with the given order as JSON body.
+  ///
+  /// # Arguments
+  ///
+  /// * `order`: Order to send.
+  /// * `modified`: Time the order was last modified at.
+  /// * `pretty`: Whether the JSON is indented.
+  fn order_response(order: &OrderStatus, modified: SystemTime, pretty: bool) -> HttpResponse<'static> {
+    let mut response = Self::json_response(order, pretty);
+    if response.status_code() != "200" {
+      return response;
+    }
+    response.set_header("ETag", &order.etag());
+    response.set_header("Last-Modified", &date::format(modified));
+    response
+  }
+
+  /// Produces the response serving the given value in JSON, or describing the failure to
+  /// serialize it with `500`.
+  ///
+  /// # Arguments
+  ///
+  /// * `value`: Value to serve.
+  /// * `pretty`: Whether the JSON is indented, compact JSON saving bandwidth.
+  fn json_response<'a, T: Serialize + ?Sized>(value: &T, pretty: bool) -> HttpResponse<'a> {
+    let response = match pretty {
+      true => HttpResponse::from_json_pretty(value),
+      false => HttpResponse::from_json(value),
+    };
+    let mut response = response.unwrap_or_else(Self::serialization_failed);
+    // Generated bodies may differ between requests, so they cannot be resumed
+    response.set_header("Accept-Ranges", "none");
+    response
+  }
+
+  /// Produces the `500` response to a value that could not be serialized, instead of
+  /// letting the handler panic.
+  ///
+  /// # Arguments
+  ///
+  /// * `e`: Error of the serialization.
+  fn serialization_failed<'a>(e: serde_json::Error) -> HttpResponse<'a> {
+    error!("Failed to serialize a response: {}", e);
+    Self::error_response("500", "internal_error", "the response could not be serialized")
+  }
+
+  /// Checks whether the client asks for indented JSON with the `pretty=1` (or
+  /// `pretty=true`) query parameter, to read responses while debugging.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request to the API.
+  fn wants_pretty(request: &HttpRequest) -> bool {
+    request
+      .query_string()
+      .is_some_and(|query| query.split('&').any(|pair| matches!(pair, "pretty=1" | "pretty=true")))
+  }
+
+  /// Produces an API error response with a JSON body describing the error.
+  ///
+  /// # Arguments
+  ///
+  /// * `status_code`: HTTP status numerical code for the response.
+  /// * `error`: Machine-readable error code.
+  /// * `message`: Human-readable description of the error.
+  pub fn error_response<'a>(status_code: &'a str, error: &str, message: &str) -> HttpResponse<'a> {
+    let body = serde_json::json!({ "error": error, "message": message }).to_string();
+    let mut headers: HashMap<&str, &str> = HashMap::new();
+    headers.insert("Content-Type", "application/json;charset=UTF-8");
+    HttpResponse::new(status_code, Some(headers), Some(body))
   }
 }
 
 impl Handler for WebServiceHandler {
-  fn handle(request: &HttpRequest) -> HttpResponse {
-    let Resource::Path(p) = &request.resource;
+  fn handle(&self, request: &HttpRequest) -> HttpResponse<'_> {
+    let path = request.path();
 
-    let route: Vec<&str> = p.split("/").collect();
+    // Match the sub-path of the orders ('/api/shipping/orders' or '/api/shipping/orders/{id}')
+    let sub_path = path.trim_end_matches('/').strip_prefix(ORDERS_PATH);
+    let order_id = sub_path
+      .and_then(|rest| rest.strip_prefix('/'))
+      .filter(|id| !id.is_empty() && !id.contains('/'));
+    // HEAD is answered like GET, the router leaving the body out
+    let method = match request.method {
+      Method::HEAD => Method::GET,
+      method => method,
+    };
 
-    match route[2] {
-      // Match the path '/api/shipping/orders'
-      "shipping" if (route.len() > 2 && route[3] == "orders") => {
-        let body = serde_json::to_string(&Self::load_json()).unwrap();
-        let mut headers: HashMap<&str, &str> = HashMap::new();
-        headers.insert("Content-Type", "application/json;charset=UTF-8");
-        HttpResponse::new("200", Some(headers), Some(body))
+    if path.trim_end_matches('/') == EXPORT_PATH {
+      return match method {
+        Method::GET => self.export_orders(),
+        _ => {
+          let message = format!("{} does not answer to {:?}", path, request.method);
+          let mut response = Self::error_response("405", "method_not_allowed", &message);
+          response.set_header("Allow", "GET, HEAD");
+          response
+        }
+      };
+    }
+
+    match (sub_path, order_id, method) {
+      // Process a request to the orders
+      (Some(""), _, Method::GET) => self.list_orders(Self::wants_pretty(request)),
+      (Some(""), _, Method::POST) => self.create_order_once(request),
+      // Process a request to a single order
+      (_, Some(id), Method::GET) => self.get_order(id, Self::wants_pretty(request)),
+      (_, Some(id), Method::PUT) => self.replace_order(id, request),
+      (_, Some(id), Method::PATCH) => self.patch_order(id, request),
+      (Some(""), _, _) | (_, Some(_), _) => {
+        let message = format!("{} does not answer to {:?}", path, request.method);
+        let mut response = Self::error_response("405", "method_not_allowed", &message);
+        let allowed: Vec<String> = self.allowed_methods(path).iter().map(|m| format!("{:?}", m)).collect();
+        response.set_header("Allow", &allowed.join(", "));
+        response
       }
-      _ => HttpResponse::new("404", None, Self::load_file("404.html")),
+      _ => Self::error_response("404", "not_found", &format!("{} does not exist", path)),
     }
   } // end fn handle()
 }
 
+/// Contents of the `robots.txt` file served when the public directory has none,
+/// allowing every crawler everywhere.
+pub const DEFAULT_ROBOTS_TXT: &str = "User-agent: *\nAllow: /\n";
+
+/// Contents of the icon served at `/favicon.ico` when none is configured: a single
+/// transparent pixel.
+pub const DEFAULT_FAVICON: [u8; 70] = [
+  // Icon header: one image, then its directory entry (1x1, 32 bits, 48 bytes at offset 22)
+  0, 0, 1, 0, 1, 0, 1, 1, 0, 0, 1, 0, 32, 0, 48, 0, 0, 0, 22, 0, 0, 0,
+  // Bitmap header: 1 pixel wide, twice 1 pixel high for the mask, 32 bits, uncompressed
+  40, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1, 0, 32, 0, 0, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+  0, 0, 0, 0,
+  // Transparent pixel, then the transparency mask padded to 4 bytes
+  0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// Represents the response to the home page when the public directory has no index file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DefaultRoot {
+  /// Page served with the given HTML contents, e.g. a welcome message.
+  Page(String),
+  /// Redirection to the given location, e.g. `/health`.
+  Redirect(String),
+}
+
 /// Represents a handler to serve static web pages.
-pub struct StaticPageHandler;
+pub struct StaticPageHandler {
+  /// Directory the static files are served from.
+  public_path: String,
+  /// Path segments that are refused even if allowed.
+  denied: Vec<String>,
+  /// Hidden path segments (starting with a dot) that may be served.
+  allowed_hidden: Vec<String>,
+  /// HTTP status numerical code used to refuse denied and hidden paths.
+  denied_status: &'static str,
+  /// Charset announced in the content type of text files.
+  charset: &'static str,
+  /// Name of the file served for the home page and directories.
+  index: String,
+  /// Contents served for the given file names when the files do not exist.
+  fallback_files: Vec<(String, String)>,
+  /// Response to the home page when there is no index file, an empty page by default.
+  default_root: Option<DefaultRoot>,
+}
 
-impl Handler for StaticPageHandler {
-  fn handle(request: &HttpRequest) -> HttpResponse {
-    // Obtain the path of the static page resource
-    let Resource::Path(p) = &request.resource;
-    let route: Vec<&str> = p.split("/").collect();
+impl Default for StaticPageHandler {
+  fn default() -> Self {
+    let default_path = format!("{}/public", env!("CARGO_MANIFEST_DIR"));
+    Self::new(env::var("PUBLIC_PATH").unwrap_or(default_path))
+  }
+}
+
+impl StaticPageHandler {
+  /// Creates a new [`StaticPageHandler`] object.
+  ///
+  /// # Arguments
+  ///
+  /// * `public_path`: Directory the static files are served from.
+  pub fn new(public_path: String) -> Self {
+    Self {
+      public_path,
+      denied: Vec::new(),
+      allowed_hidden: Vec::new(),
+      denied_status: "403",
+      charset: "utf-8",
+      index: "index.html".to_string(),
+      fallback_files: Vec::new(),
+      default_root: None,
+    }
+  }
+
+  /// Gets the directory the static files are served from.
+  pub fn public_path(&self) -> &str {
+    &self.public_path
+  }
+
+  /// Sets the contents served for the given file when it does not exist in the public directory.
+  ///
+  /// # Arguments
+  ///
+  /// * `file_name`: Name of the file relative to the public directory (e.g. `robots.txt`).
+  /// * `contents`: Contents served in place of the missing file.
+  pub fn with_fallback_file(mut self, file_name: &str, contents: String) -> Self {
+    self.fallback_files.retain(|(name, _)| name != file_name);
+    self.fallback_files.push((file_name.to_string(), contents));
+    self
+  }
+
+  /// Sets the response to the home page when the public directory has no index file.
+  ///
+  /// # Arguments
+  ///
+  /// * `default_root`: Page served or redirection.
+  pub fn with_default_root(mut self, default_root: DefaultRoot) -> Self {
+    self.default_root = Some(default_root);
+    self
+  }
+
+  /// Sets the name of the file served for the home page and directories (`index.html` by default).
+  ///
+  /// # Arguments
+  ///
+  /// * `index`: Name of the index file (e.g. `home.html`).
+  pub fn with_index(mut self, index: String) -> Self {
+    self.index = index;
+    self
+  }
+
+  /// Sets the charset announced in the content type of text files (`utf-8` by default).
+  ///
+  /// # Arguments
+  ///
+  /// * `charset`: Name of the charset.
+  pub fn with_charset(mut self, charset: &'static str) -> Self {
+    self.charset = charset;
+    self
+  }
+
+  /// Produces a successful response serving the given contents as an HTML page.
+  ///
+  /// # Arguments
+  ///
+  /// * `contents`: Contents of the page, if it exists.
+  fn page(&self, contents: Option<Vec<u8>>) -> HttpResponse<'_> {
+    let mut response = HttpResponse::with_bytes("200", None, contents);
+    response.set_header("Content-Type", &mime::with_charset("text/html", self.charset));
+    response
+  }
 
-    match route[1] {
-      // Serve the home page (index.html)
-      "" => HttpResponse::new("200", None, Self::load_file("index.html")),
+  /// Sets the path segments that are refused even if allowed.
+  ///
+  /// # Arguments
+  ///
+  /// * `denied`: Names of the denied files or directories (e.g. `.git`).
+  pub fn with_denied(mut self, denied: Vec<String>) -> Self {
+    self.denied = denied;
+    self
+  }
+
+  /// Sets the hidden path segments (starting with a dot) that may be served.
+  ///
+  /// # Arguments
+  ///
+  /// * `allowed_hidden`: Names of the allowed hidden files or directories (e.g. `.well-known`).
+  pub fn with_allowed_hidden(mut self, allowed_hidden: Vec<String>) -> Self {
+    self.allowed_hidden = allowed_hidden;
+    self
+  }
+
+  /// Sets the HTTP status used to refuse denied and hidden paths (`403` by default).
+  ///
+  /// # Arguments
+  ///
+  /// * `denied_status`: HTTP status numerical code, e.g. `404` to hide existence.
+  pub fn with_denied_status(mut self, denied_status: &'static str) -> Self {
+    self.denied_status = denied_status;
+    self
+  }
+
+  /// Checks whether any segment of the given path is denied or hidden and not allowed.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Path of the requested resource.
+  fn is_denied(&self, path: &str) -> bool {
+    path.split('/').any(|segment| {
+      let denied = self.denied.iter().any(|denied| denied == segment);
+      let hidden = segment.starts_with('.') && !self.allowed_hidden.iter().any(|a| a == segment);
+      denied || hidden
+    })
+  }
+
+  /// Loads the raw contents of the specified file from the public directory.
+  ///
+  /// # Arguments
+  ///
+  /// * `file_name`: Name of the file to load relative to the public directory.
+  fn read_file(&self, file_name: &str) -> Option<Vec<u8>> {
+    fs::read(format!("{}/{}", self.public_path, file_name)).ok()
+  }
+
+  /// Checks whether the specified file exists in the public directory, without reading it.
+  ///
+  /// # Arguments
+  ///
+  /// * `file_name`: Name of the file relative to the public directory.
+  fn has_file(&self, file_name: &str) -> bool {
+    Path::new(&self.public_path).join(file_name).is_file()
+  }
+
+  /// Checks whether [`serve`](Self::serve) finds a page at the given path, from the file
+  /// metadata only: the files are neither read nor sent.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request for the file.
+  /// * `p`: Path of the file relative to the public directory, starting with `/`.
+  pub fn exists(&self, request: &HttpRequest, p: &str) -> bool {
+    if self.is_denied(p) {
+      return false;
+    }
+
+    match p.split_once('/').map_or("", |(_, path)| path) {
+      // The home and health pages are always served, even when empty
+      "" | "health" => true,
+      path if Path::new(&self.public_path).join(path).is_dir() => {
+        self.has_file(&format!("{}/{}", path, self.index))
+      }
+      path => {
+        (compress::accepts(request, "gzip") && self.has_file(&format!("{}.gz", path)))
+          || self.has_file(path)
+          || self.fallback_files.iter().any(|(name, _)| name == path)
+      }
+    }
+  }
+
+  /// Gets the first and last byte positions of the range a `Range` header asks for in a
+  /// body of the given length, e.g. `bytes=0-499`, `bytes=500-` or `bytes=-500`.
+  ///
+  /// Returns `None` for malformed headers and multiple ranges, which get the full body,
+  /// and `Some(None)` for a range starting past the end of the body.
+  ///
+  /// # Arguments
+  ///
+  /// * `range`: Value of the `Range` header.
+  /// * `len`: Length of the body, in bytes.
+  fn byte_range(range: &str, len: usize) -> Option<Option<(usize, usize)>> {
+    let (first, last) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+
+    // A suffix range asks for the last bytes of the body
+    if first.is_empty() {
+      let suffix: usize = last.parse().ok()?;
+      return Some((suffix > 0 && len > 0).then(|| (len - suffix.min(len), len - 1)));
+    }
+
+    let first: usize = first.parse().ok()?;
+    let last: usize = match last {
+      "" => usize::MAX,
+      last => last.parse().ok()?,
+    };
+    if last < first {
+      return None;
+    }
+    Some((first < len).then(|| (first, last.min(len - 1))))
+  }
+
+  /// Narrows the given full response for a file to the byte range the request asks for
+  /// with a `Range` header, if any, advertising the support with `Accept-Ranges: bytes`.
+  /// A range outside the file gets an empty body, without the headers describing one.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request for the file.
+  /// * `response`: Successful HTTP response with the whole file.
+  fn with_range<'r>(request: &HttpRequest, mut response: HttpResponse<'r>) -> HttpResponse<'r> {
+    response.set_header("Accept-Ranges", "bytes");
+    let len = response.body_bytes().len();
+    let Some(range) = request.header("Range").and_then(|range| Self::byte_range(range, len)) else {
+      return response;
+    };
+
+    match range {
+      Some((first, last)) => {
+        let part = response.body_bytes()[first..=last].to_vec();
+        response.set_body(part);
+        response.set_header("Content-Range", &format!("bytes {}-{}/{}", first, last, len));
+        response.with_status(206)
+      }
+      None => {
+        response.set_body(Vec::new());
+        for header in ["Content-Type", "Content-Encoding", "Vary"] {
+          response.remove_header(header);
+        }
+        response.set_header("Content-Range", &format!("bytes */{}", len));
+        response.with_status(416)
+      }
+    }
+  }
+
+  /// Serves the file at the given path relative to the public directory.
+  ///
+  /// Paths with a hidden segment, `..` included, are refused, so the files outside
+  /// the public directory cannot be reached.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request for the file.
+  /// * `p`: Path of the file relative to the public directory, starting with `/`.
+  pub fn serve(&self, request: &HttpRequest, p: &str) -> HttpResponse<'_> {
+    let route: Vec<&str> = p.splitn(2, '/').collect();
+
+    // Refuse denied and hidden paths whether they exist or not
+    if self.is_denied(p) {
+      return HttpResponse::new(self.denied_status, None, None);
+    }
+
+    match route.get(1).copied().unwrap_or("") {
+      // Serve the home page (index.html by default)
+      "" => match (self.read_file(&self.index), &self.default_root) {
+        (Some(contents), _) => self.page(Some(contents)),
+        (None, Some(DefaultRoot::Page(contents))) => self.page(Some(contents.clone().into_bytes())),
+        (None, Some(DefaultRoot::Redirect(location))) => HttpResponse::redirect(location),
+        (None, None) => self.page(None),
+      },
       // Serve the health page (health.html)
-      "health" => HttpResponse::new("200", None, Self::load_file("health.html")),
+      "health" => self.page(self.read_file("health.html")),
+      // Serve the index file of a directory
+      path if Path::new(&self.public_path).join(path).is_dir() => {
+        match self.read_file(&format!("{}/{}", path, self.index)) {
+          Some(contents) => self.page(Some(contents)),
+          None => Self::not_found(request),
+        }
+      }
       // Serve any other page if the file exists
-      path => match Self::load_file(path) {
-        Some(contents) => {
-          let mut headers: HashMap<&str, &str> = HashMap::new();
-
-          // Set a header according to the file extension
-          match Path::new(path).extension().unwrap().to_str() {
-            Some("css") => headers.insert("Content-Type", "text/css"),
-            Some("js") => headers.insert("Content-Type", "text/javascript"),
-            None | _ => headers.insert("Content-Type", "text/html"),
-          };
-
-          HttpResponse::new("200", Some(headers), Some(contents))
-        } // end some(contents) for an existing file
-        // The requested page does not have a correspoding file, so respond with "Not Found"
-        None => HttpResponse::new("404", None, Self::load_file("404.html")),
-      },
+      path => {
+        // Prefer a precompressed copy of the file when the client can decode it
+        let gzipped = if compress::accepts(request, "gzip") {
+          self.read_file(&format!("{}.gz", path))
+        } else {
+          None
+        };
+        let encoded = gzipped.is_some();
+
+        let fallback = || {
+          let fallback = self.fallback_files.iter().find(|(name, _)| name == path);
+          fallback.map(|(_, contents)| contents.clone().into_bytes())
+        };
+
+        match gzipped.or_else(|| self.read_file(path)).or_else(fallback) {
+          Some(contents) => {
+            // Set a header according to the file extension, or its contents if it has none
+            let content_type = match Path::new(path).extension().filter(|e| !e.is_empty()) {
+              Some(extension) => mime::content_type(extension.to_str()),
+              None if !encoded => mime::sniff(&contents),
+              None => mime::DEFAULT_CONTENT_TYPE,
+            };
+
+            let mut response = HttpResponse::with_bytes("200", None, Some(contents));
+            response.set_header("Content-Type", &mime::with_charset(content_type, self.charset));
+            if encoded {
+              response.set_header("Content-Encoding", "gzip");
+              response.set_header("Vary", "Accept-Encoding");
+            }
+
+            Self::with_range(request, response)
+          } // end some(contents) for an existing file
+          // The requested page does not have a correspoding file, so respond with "Not Found"
+          None => Self::not_found(request),
+        }
+      }
     } // end match route[]
-  } // end fn handle()
+  } // end fn serve()
+}
+
+impl Handler for StaticPageHandler {
+  fn handle(&self, request: &HttpRequest) -> HttpResponse<'_> {
+    // Obtain the path of the static page resource
+    let Resource::Path(p) = &request.resource;
+    self.serve(request, p)
+  }
 }
 
 /// Represents a handler to serve "404 Not Found" pages.
 pub struct PageNotFoundHandler;
 
 impl Handler for PageNotFoundHandler {
-  fn handle(_request: &HttpRequest) -> HttpResponse {
-    HttpResponse::new("404", None, Self::load_file("404.html"))
+  fn handle(&self, request: &HttpRequest) -> HttpResponse<'_> {
+    Self::not_found(request)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn request(raw: &str) -> HttpRequest {
+    raw.to_string().into()
+  }
+
+  #[test]
+  fn test_handler_with_built_requests() {
+    let handler = WebServiceHandler::new(Arc::new(Mutex::new(Vec::new())), false);
+    let create = HttpRequest::new(Method::POST, "/api/shipping/orders")
+      .with_header("Host", "localhost")
+      .with_body(r#"{"order_id":7,"order_date":"1 Jan 2020","order_status":"Pending"}"#);
+    let fetch = HttpRequest::new(Method::GET, "/api/shipping/orders/7?pretty=1");
+
+    let created = handler.handle(&create);
+    let fetched = handler.handle(&fetch);
+
+    assert_eq!(created.status_code(), "201");
+    assert_eq!(fetched.status_code(), "200");
+    assert!(fetched.body().contains("\n  \"order_id\": 7,"));
+  }
+
+  #[test]
+  fn test_create_with_same_idempotency_key_once() {
+    let orders = Arc::new(Mutex::new(Vec::new()));
+    let handler = WebServiceHandler::new(Arc::clone(&orders), false);
+    let create = |id: i32| {
+      HttpRequest::new(Method::POST, "/api/shipping/orders")
+        .with_header("Idempotency-Key", "retry-1")
+        .with_body(format!(r#"{{"order_id":{},"order_date":"1 Jan 2020","order_status":"Pending"}}"#, id))
+    };
+
+    let first = handler.handle(&create(1));
+    let retried = handler.handle(&create(1));
+    let reused = handler.handle(&create(2));
+
+    assert_eq!(first.status_code(), "201");
+    assert_eq!(retried, first);
+    assert_eq!(reused, first);
+    assert_eq!(*orders.lock().unwrap(), vec![order(1, "1 Jan 2020", "Pending")]);
+  }
+
+  #[test]
+  fn test_created_order_visible_without_file() {
+    let orders = Arc::new(Mutex::new(Vec::new()));
+    let handler = WebServiceHandler::new(Arc::clone(&orders), false);
+    let file_before = fs::read_to_string(WebServiceHandler::data_file()).ok();
+
+    let create = request(
+      "POST /api/shipping/orders HTTP/1.1\r\nHost: localhost\r\n\r\n\
+       {\"order_id\":3,\"order_date\":\"3 Mar 2020\",\"order_status\":\"Pending\"}",
+    );
+    let created = handler.handle(&create);
+    assert_eq!(created.header("Location"), Some("/api/shipping/orders/3"));
+    let created: String = created.into();
+    assert!(created.starts_with("HTTP/1.1 201 Created\r\n"));
+
+    let list = request("GET /api/shipping/orders HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let listed = handler.handle(&list);
+    assert_eq!(
+      listed.body(),
+      "[{\"order_id\":3,\"order_date\":\"3 Mar 2020\",\"order_status\":\"Pending\"}]"
+    );
+
+    assert_eq!(orders.lock().unwrap().len(), 1);
+    assert_eq!(fs::read_to_string(WebServiceHandler::data_file()).ok(), file_before);
+  }
+
+  #[test]
+  fn test_create_duplicate_order_conflict() {
+    let orders = Arc::new(Mutex::new(vec![order(1, "1 Jan 2020", "Pending")]));
+    let handler = WebServiceHandler::new(Arc::clone(&orders), false);
+    let create = |id: i32| {
+      request(&format!(
+        "POST /api/shipping/orders HTTP/1.1\r\nHost: localhost\r\n\r\n\
+         {{\"order_id\":{},\"order_date\":\"3 Mar 2020\",\"order_status\":\"Shipped\"}}",
+        id
+      ))
+    };
+
+    let created = handler.handle(&create(2));
+    let duplicate: String = handler.handle(&create(1)).into();
+
+    assert_eq!(created.status_code(), "201");
+    assert!(duplicate.starts_with("HTTP/1.1 409 Conflict\r\n"));
+    assert!(duplicate.contains("\"error\":\"duplicate_order\""));
+    let orders = orders.lock().unwrap();
+    assert_eq!(orders.len(), 2);
+    assert_eq!(orders[0].order_status, "Pending");
+  }
+
+  #[test]
+  fn test_orders_served_from_json() {
+    let orders = vec![order(1, "1 Jan 2020", "Pending"), order(2, "2 Feb 2020", "Shipped")];
+
+    let response = HttpResponse::from_json(&orders).unwrap();
+
+    assert_eq!(response.header("Content-Type"), Some("application/json;charset=UTF-8"));
+    assert_eq!(
+      response.body(),
+      "[{\"order_id\":1,\"order_date\":\"1 Jan 2020\",\"order_status\":\"Pending\"},\
+       {\"order_id\":2,\"order_date\":\"2 Feb 2020\",\"order_status\":\"Shipped\"}]"
+    );
+  }
+
+  #[test]
+  fn test_pretty_json_on_request() {
+    let orders = Arc::new(Mutex::new(vec![order(1, "1 Jan 2020", "Pending")]));
+    let handler = WebServiceHandler::new(orders, false);
+
+    let compact = handler.handle(&request("GET /api/shipping/orders/1 HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    let pretty = handler.handle(&request("GET /api/shipping/orders/1?pretty=1 HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(compact.body(), "{\"order_id\":1,\"order_date\":\"1 Jan 2020\",\"order_status\":\"Pending\"}");
+    assert_eq!(
+      pretty.body(),
+      "{\n  \"order_id\": 1,\n  \"order_date\": \"1 Jan 2020\",\n  \"order_status\": \"Pending\"\n}"
+    );
+    assert_eq!(pretty.header("ETag"), compact.header("ETag"));
+  }
+
+  #[test]
+  fn test_create_order_invalid_json() {
+    let handler = WebServiceHandler::new(Arc::new(Mutex::new(Vec::new())), false);
+
+    let create = request("POST /api/shipping/orders HTTP/1.1\r\nHost: localhost\r\n\r\nnot json");
+    let response: String = handler.handle(&create).into();
+
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(response.contains("\"error\":\"invalid_json\""));
+  }
+
+  #[test]
+  fn test_patch_order_status_only() {
+    let orders = Arc::new(Mutex::new(vec![order(1, "1 Jan 2020", "Pending"), order(2, "2 Feb 2020", "Pending")]));
+    let handler = WebServiceHandler::new(Arc::clone(&orders), false);
+
+    let patch = request(
+      "PATCH /api/shipping/orders/2 HTTP/1.1\r\nHost: localhost\r\n\r\n{\"order_status\":\"Shipped\"}",
+    );
+    let patched = handler.handle(&patch);
+    let fetched = handler.handle(&request("GET /api/shipping/orders/2 HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(patched.status_code(), "200");
+    assert_eq!(
+      fetched.body(),
+      "{\"order_id\":2,\"order_date\":\"2 Feb 2020\",\"order_status\":\"Shipped\"}"
+    );
+    assert_eq!(orders.lock().unwrap()[0], order(1, "1 Jan 2020", "Pending"));
+  }
+
+  #[test]
+  fn test_patch_order_rejected() {
+    let orders = Arc::new(Mutex::new(vec![order(1, "1 Jan 2020", "Pending")]));
+    let handler = WebServiceHandler::new(Arc::clone(&orders), false);
+    let patch = |target: &str, body: &str| {
+      let raw = format!("PATCH /api/shipping/orders/{} HTTP/1.1\r\nHost: localhost\r\n\r\n{}", target, body);
+      let response = handler.handle(&request(&raw));
+      (response.status_code().to_string(), response.body().to_string())
+    };
+
+    let (status, body) = patch("1", "{\"order_id\":7}");
+    assert_eq!(status, "400");
+    assert!(body.contains("\"error\":\"unknown_field\""));
+    let (status, body) = patch("1", "{\"order_status\":\"Lost\"}");
+    assert_eq!(status, "400");
+    assert!(body.contains("\"error\":\"invalid_order\""));
+    let (status, _) = patch("1", "[1]");
+    assert_eq!(status, "400");
+    let (status, _) = patch("9", "{\"order_status\":\"Shipped\"}");
+    assert_eq!(status, "404");
+    let post = handler.handle(&request("POST /api/shipping/orders/1 HTTP/1.1\r\nHost: localhost\r\n\r\n{}"));
+    assert_eq!(post.status_code(), "405");
+
+    assert_eq!(orders.lock().unwrap()[0], order(1, "1 Jan 2020", "Pending"));
+  }
+
+  #[test]
+  fn test_update_with_matching_etag() {
+    let orders = Arc::new(Mutex::new(vec![order(1, "1 Jan 2020", "Pending")]));
+    let handler = WebServiceHandler::new(Arc::clone(&orders), false);
+
+    let read = handler.handle(&request("GET /api/shipping/orders/1 HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    let etag = read.header("ETag").unwrap().to_string();
+    let raw = format!(
+      "PUT /api/shipping/orders/1 HTTP/1.1\r\nHost: localhost\r\nIf-Match: {}\r\n\r\n{}",
+      etag, "{\"order_id\":1,\"order_date\":\"1 Jan 2020\",\"order_status\":\"Shipped\"}"
+    );
+    let written = handler.handle(&request(&raw));
+
+    assert_eq!(written.status_code(), "200");
+    assert_ne!(written.header("ETag"), Some(etag.as_str()));
+    assert_eq!(orders.lock().unwrap()[0], order(1, "1 Jan 2020", "Shipped"));
+  }
+
+  #[test]
+  fn test_update_with_stale_etag_rejected() {
+    let orders = Arc::new(Mutex::new(vec![order(1, "1 Jan 2020", "Pending")]));
+    let handler = WebServiceHandler::new(Arc::clone(&orders), false);
+    let stale = order(1, "1 Jan 2020", "Shipped").etag();
+
+    let raw = format!(
+      "PATCH /api/shipping/orders/1 HTTP/1.1\r\nHost: localhost\r\nIf-Match: {}\r\n\r\n{}",
+      stale, "{\"order_status\":\"Delivered\"}"
+    );
+    let response: String = handler.handle(&request(&raw)).into();
+
+    assert!(response.starts_with("HTTP/1.1 412 Precondition Failed\r\n"));
+    assert_eq!(orders.lock().unwrap()[0], order(1, "1 Jan 2020", "Pending"));
+  }
+
+  #[test]
+  fn test_update_unmodified_since_proceeds() {
+    let orders = Arc::new(Mutex::new(vec![order(1, "1 Jan 2020", "Pending")]));
+    let handler = WebServiceHandler::new(Arc::clone(&orders), false);
+
+    let read = handler.handle(&request("GET /api/shipping/orders/1 HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    let last_modified = read.header("Last-Modified").unwrap().to_string();
+    let raw = format!(
+      "PATCH /api/shipping/orders/1 HTTP/1.1\r\nHost: localhost\r\nIf-Unmodified-Since: {}\r\n\r\n{}",
+      last_modified, "{\"order_status\":\"Shipped\"}"
+    );
+    let written = handler.handle(&request(&raw));
+
+    assert_eq!(written.status_code(), "200");
+    assert_eq!(orders.lock().unwrap()[0], order(1, "1 Jan 2020", "Shipped"));
+  }
+
+  #[test]
+  fn test_update_modified_since_rejected() {
+    let orders = Arc::new(Mutex::new(vec![order(1, "1 Jan 2020", "Pending")]));
+    let handler = WebServiceHandler::new(Arc::clone(&orders), false);
+
+    let raw = format!(
+      "PATCH /api/shipping/orders/1 HTTP/1.1\r\nHost: localhost\r\nIf-Unmodified-Since: {}\r\n\r\n{}",
+      "Sun, 06 Nov 1994 08:49:37 GMT", "{\"order_status\":\"Delivered\"}"
+    );
+    let response = handler.handle(&request(&raw));
+    let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+
+    assert_eq!(response.status_code(), "412");
+    assert_eq!(body["error"], "precondition_failed");
+    assert_eq!(orders.lock().unwrap()[0], order(1, "1 Jan 2020", "Pending"));
+  }
+
+  #[test]
+  fn test_update_extreme_unmodified_since_ignored() {
+    let orders = Arc::new(Mutex::new(vec![order(1, "1 Jan 2020", "Pending")]));
+    let handler = WebServiceHandler::new(Arc::clone(&orders), false);
+
+    let raw = format!(
+      "PATCH /api/shipping/orders/1 HTTP/1.1\r\nHost: localhost\r\nIf-Unmodified-Since: {}\r\n\r\n{}",
+      "Sun, 06 Nov 300000000000 08:49:37 GMT", "{\"order_status\":\"Shipped\"}"
+    );
+    let written = handler.handle(&request(&raw));
+    let read = handler.handle(&request("GET /api/shipping/orders/1 HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(written.status_code(), "200");
+    assert_eq!(read.status_code(), "200");
+    assert!(!orders.is_poisoned());
+  }
+
+  #[test]
+  fn test_unknown_api_paths_json_not_found() {
+    let handler = WebServiceHandler::new(Arc::new(Mutex::new(Vec::new())), false);
+
+    for path in ["/api", "/api/", "/api/unknown", "/api/shipping", "/api/shipping/orders/1/items"] {
+      let raw = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+      let response = handler.handle(&request(&raw));
+      let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+
+      assert_eq!(response.status_code(), "404", "{}", path);
+      assert_eq!(body["error"], "not_found");
+    }
+  }
+
+  #[test]
+  fn test_unserializable_value_internal_error() {
+    // JSON objects only have string keys, so a map keyed by pairs cannot be serialized
+    let value: HashMap<(i32, i32), i32> = HashMap::from([((1, 2), 3)]);
+
+    let response = WebServiceHandler::json_response(&value, false);
+    let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+
+    assert_eq!(response.status_code(), "500");
+    assert_eq!(body["error"], "internal_error");
+  }
+
+  #[test]
+  fn test_export_streams_one_order_per_line() {
+    let orders: Vec<OrderStatus> = (1..=50).map(|id| order(id, "1 Jan 2020", "Pending")).collect();
+    let handler = WebServiceHandler::new(Arc::new(Mutex::new(orders)), false);
+
+    let mut response = handler.handle(&request("GET /api/shipping/export HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    assert!(response.is_streamed());
+    assert_eq!(response.header("Content-Type"), Some("application/x-ndjson"));
+    response.buffer_body().unwrap();
+
+    let lines: Vec<&str> = response.body().lines().collect();
+    assert_eq!(lines.len(), 50);
+    for (index, line) in lines.iter().enumerate() {
+      let order: OrderStatus = serde_json::from_str(line).unwrap();
+      assert_eq!(order.order_id, index as i32 + 1);
+    }
+  }
+
+  #[test]
+  fn test_orders_path_with_query_and_slash() {
+    let orders = Arc::new(Mutex::new(vec![order(1, "1 Jan 2020", "Pending")]));
+    let handler = WebServiceHandler::new(orders, false);
+
+    let listed = handler.handle(&request("GET /api/shipping/orders/?page=1 HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    let rejected = handler.handle(&request("DELETE /api/shipping/orders HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(listed.status_code(), "200");
+    assert!(listed.body().starts_with("[{\"order_id\":1,"));
+    assert_eq!(rejected.status_code(), "405");
+    assert_eq!(rejected.header("Allow"), Some("GET, HEAD, POST"));
+  }
+
+  fn order(order_id: i32, order_date: &str, order_status: &str) -> OrderStatus {
+    OrderStatus {
+      order_id,
+      order_date: order_date.to_string(),
+      order_status: order_status.to_string(),
+    }
   }
-}
\ No newline at end of file
+
+  /// Creates an empty public directory unique to the calling test.
+  fn public_dir(name: &str) -> String {
+    let dir = env::temp_dir().join(format!("httpserver-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir.to_str().unwrap().to_string()
+  }
+
+  #[test]
+  fn test_precompressed_file_for_gzip_client() {
+    let dir = public_dir("gzip-client");
+    fs::write(format!("{}/app.js", dir), "console.log(1);").unwrap();
+    fs::write(format!("{}/app.js.gz", dir), [0x1f, 0x8b, 0x08, 0x00]).unwrap();
+    let handler = StaticPageHandler::new(dir);
+
+    let response = handler.handle(&request(
+      "GET /app.js HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: deflate, gzip\r\n\r\n",
+    ));
+    let http = String::from(response.clone());
+
+    assert_eq!(response.body_bytes(), [0x1f, 0x8b, 0x08, 0x00]);
+    assert!(http.contains("Content-Encoding:gzip\r\n"));
+    assert!(http.contains("Content-Type:text/javascript; charset=utf-8\r\n"));
+  }
+
+  #[test]
+  fn test_plain_file_for_non_gzip_client() {
+    let dir = public_dir("plain-client");
+    fs::write(format!("{}/app.js", dir), "console.log(1);").unwrap();
+    fs::write(format!("{}/app.js.gz", dir), [0x1f, 0x8b, 0x08, 0x00]).unwrap();
+    let handler = StaticPageHandler::new(dir);
+
+    let response = handler.handle(&request(
+      "GET /app.js HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip;q=0\r\n\r\n",
+    ));
+    let http = String::from(response.clone());
+
+    assert_eq!(response.body(), "console.log(1);");
+    assert!(!http.contains("Content-Encoding"));
+    assert!(http.contains("Content-Type:text/javascript; charset=utf-8\r\n"));
+  }
+
+  #[test]
+  fn test_static_file_accepts_ranges() {
+    let dir = public_dir("ranges");
+    fs::write(format!("{}/notes.txt", dir), "0123456789").unwrap();
+    let handler = StaticPageHandler::new(dir);
+
+    let full = handler.handle(&request("GET /notes.txt HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    let part = handler.handle(&request("GET /notes.txt HTTP/1.1\r\nHost: localhost\r\nRange: bytes=2-4\r\n\r\n"));
+    let suffix = handler.handle(&request("GET /notes.txt HTTP/1.1\r\nHost: localhost\r\nRange: bytes=-3\r\n\r\n"));
+    let outside = handler.handle(&request("GET /notes.txt HTTP/1.1\r\nHost: localhost\r\nRange: bytes=20-\r\n\r\n"));
+
+    assert_eq!(full.status_code(), "200");
+    assert_eq!(full.header("Accept-Ranges"), Some("bytes"));
+    assert_eq!(part.status_code(), "206");
+    assert_eq!(part.body(), "234");
+    assert_eq!(part.header("Content-Range"), Some("bytes 2-4/10"));
+    assert_eq!(suffix.body(), "789");
+    assert_eq!(outside.status_code(), "416");
+    assert_eq!(outside.header("Content-Range"), Some("bytes */10"));
+    assert_eq!(outside.header("Content-Type"), None);
+  }
+
+  #[test]
+  fn test_unsatisfiable_range_of_precompressed_file() {
+    let dir = public_dir("ranges-gzip");
+    fs::write(format!("{}/app.js.gz", dir), [0x1f, 0x8b, 0x08, 0x00]).unwrap();
+    let handler = StaticPageHandler::new(dir);
+
+    let response = handler.handle(&request(
+      "GET /app.js HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\nRange: bytes=20-\r\n\r\n",
+    ));
+
+    assert_eq!(response.status_code(), "416");
+    assert_eq!(response.body_bytes().len(), 0);
+    assert_eq!(response.header("Content-Type"), None);
+    assert_eq!(response.header("Content-Encoding"), None);
+    assert_eq!(response.header("Vary"), None);
+  }
+
+  #[test]
+  fn test_charset_on_text_types_only() {
+    let dir = public_dir("charset");
+    fs::write(format!("{}/page.html", dir), "<p>hi</p>").unwrap();
+    fs::write(format!("{}/logo.png", dir), [0x89, 0x50, 0x4e, 0x47]).unwrap();
+    let handler = StaticPageHandler::new(dir);
+
+    let page = handler.handle(&request("GET /page.html HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    let logo = handler.handle(&request("GET /logo.png HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(page.header("Content-Type"), Some("text/html; charset=utf-8"));
+    assert_eq!(logo.header("Content-Type"), Some("image/png"));
+  }
+
+  #[test]
+  fn test_extensionless_files_sniffed() {
+    let dir = public_dir("sniff");
+    fs::write(format!("{}/LICENSE", dir), "MIT License\n\nCopyright (c)").unwrap();
+    fs::write(format!("{}/blob", dir), [0x7f, 0x45, 0x4c, 0x46, 0x00, 0x01]).unwrap();
+    let handler = StaticPageHandler::new(dir);
+
+    let license = handler.handle(&request("GET /LICENSE HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    let blob = handler.handle(&request("GET /blob HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(license.status_code(), "200");
+    assert_eq!(license.header("Content-Type"), Some("text/plain; charset=utf-8"));
+    assert_eq!(blob.status_code(), "200");
+    assert_eq!(blob.header("Content-Type"), Some("application/octet-stream"));
+  }
+
+  #[test]
+  fn test_path_without_extension_served() {
+    let dir = public_dir("no-extension");
+    fs::write(format!("{}/CHANGELOG", dir), "<!doctype html><h1>Changes</h1>").unwrap();
+    fs::write(format!("{}/NOTES.", dir), "trailing dot").unwrap();
+    let handler = StaticPageHandler::new(dir);
+
+    let changelog = handler.handle(&request("GET /CHANGELOG HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    let notes = handler.handle(&request("GET /NOTES. HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(changelog.status_code(), "200");
+    assert_eq!(changelog.header("Content-Type"), Some("text/html; charset=utf-8"));
+    assert_eq!(notes.status_code(), "200");
+    assert_eq!(notes.header("Content-Type"), Some("text/plain; charset=utf-8"));
+  }
+
+  #[test]
+  fn test_configured_index_file() {
+    let dir = public_dir("index");
+    fs::write(format!("{}/home.html", dir), "<h1>home</h1>").unwrap();
+    fs::write(format!("{}/index.html", dir), "<h1>index</h1>").unwrap();
+    fs::create_dir_all(format!("{}/docs", dir)).unwrap();
+    fs::write(format!("{}/docs/home.html", dir), "<h1>docs</h1>").unwrap();
+    fs::create_dir_all(format!("{}/empty", dir)).unwrap();
+    let handler = StaticPageHandler::new(dir).with_index("home.html".to_string());
+
+    let home = handler.handle(&request("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    let docs = handler.handle(&request("GET /docs HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    let empty = handler.handle(&request("GET /empty HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(home.body(), "<h1>home</h1>");
+    assert_eq!(docs.body(), "<h1>docs</h1>");
+    assert_eq!(docs.header("Content-Type"), Some("text/html; charset=utf-8"));
+    assert_eq!(empty.status_code(), "404");
+  }
+
+  #[test]
+  fn test_default_root_without_index() {
+    let dir = public_dir("default-root");
+    let welcome = StaticPageHandler::new(dir.clone()).with_default_root(DefaultRoot::Page("<h1>Welcome</h1>".to_string()));
+    let redirect = StaticPageHandler::new(dir.clone()).with_default_root(DefaultRoot::Redirect("/health".to_string()));
+    let home = request("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert_eq!(welcome.handle(&home).body(), "<h1>Welcome</h1>");
+    assert_eq!(redirect.handle(&home).status_code(), "302");
+    assert_eq!(redirect.handle(&home).header("Location"), Some("/health"));
+
+    fs::write(format!("{}/index.html", dir), "<h1>index</h1>").unwrap();
+    assert_eq!(welcome.handle(&home).body(), "<h1>index</h1>");
+  }
+
+  #[test]
+  fn test_exists_from_metadata() {
+    let dir = public_dir("exists");
+    fs::create_dir_all(format!("{}/docs", dir)).unwrap();
+    fs::create_dir_all(format!("{}/empty", dir)).unwrap();
+    fs::write(format!("{}/docs/index.html", dir), "<h1>docs</h1>").unwrap();
+    fs::write(format!("{}/app.js.gz", dir), [0x1f, 0x8b, 0x08, 0x00]).unwrap();
+    fs::write(format!("{}/.env", dir), "SECRET=1").unwrap();
+    let handler = StaticPageHandler::new(dir)
+      .with_denied(vec![".env".to_string()])
+      .with_fallback_file("robots.txt", DEFAULT_ROBOTS_TXT.to_string())
+      .with_default_root(DefaultRoot::Redirect("/health".to_string()));
+    let plain = request("POST / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let gzip = request("POST / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\n\r\n");
+
+    for path in ["/", "/health", "/docs", "/robots.txt"] {
+      assert!(handler.exists(&plain, path), "{}", path);
+    }
+    for path in ["/empty", "/missing.html", "/.env", "/app.js"] {
+      assert!(!handler.exists(&plain, path), "{}", path);
+    }
+    assert!(handler.exists(&gzip, "/app.js"));
+  }
+
+  #[test]
+  fn test_robots_served_from_disk() {
+    let dir = public_dir("robots-disk");
+    fs::write(format!("{}/robots.txt", dir), "User-agent: *\nDisallow: /private\n").unwrap();
+    let handler = StaticPageHandler::new(dir).with_fallback_file("robots.txt", DEFAULT_ROBOTS_TXT.to_string());
+
+    let robots = handler.handle(&request("GET /robots.txt HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(robots.body(), "User-agent: *\nDisallow: /private\n");
+    assert_eq!(robots.header("Content-Type"), Some("text/plain; charset=utf-8"));
+  }
+
+  #[test]
+  fn test_builtin_defaults_when_missing() {
+    let sitemap = "<?xml version=\"1.0\"?><urlset></urlset>";
+    let handler = StaticPageHandler::new(public_dir("robots-default"))
+      .with_fallback_file("robots.txt", DEFAULT_ROBOTS_TXT.to_string())
+      .with_fallback_file("sitemap.xml", sitemap.to_string());
+
+    let robots = handler.handle(&request("GET /robots.txt HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    let map = handler.handle(&request("GET /sitemap.xml HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(robots.status_code(), "200");
+    assert_eq!(robots.body(), DEFAULT_ROBOTS_TXT);
+    assert_eq!(map.body(), sitemap);
+    assert_eq!(map.header("Content-Type"), Some("application/xml"));
+  }
+
+  #[test]
+  fn test_missing_robots_without_default_not_found() {
+    let handler = StaticPageHandler::new(public_dir("robots-none"));
+    let robots = handler.handle(&request("GET /robots.txt HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(robots.status_code(), "404");
+  }
+
+  #[test]
+  fn test_configured_charset() {
+    let dir = public_dir("charset-configured");
+    fs::write(format!("{}/notes.txt", dir), "hi").unwrap();
+    let handler = StaticPageHandler::new(dir).with_charset("iso-8859-1");
+
+    let notes = handler.handle(&request("GET /notes.txt HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(notes.header("Content-Type"), Some("text/plain; charset=iso-8859-1"));
+  }
+
+  #[test]
+  fn test_denied_dotfile_forbidden() {
+    let dir = public_dir("denied");
+    fs::write(format!("{}/.env", dir), "SECRET=1").unwrap();
+    let handler = StaticPageHandler::new(dir).with_denied(vec![".env".to_string(), ".git".to_string()]);
+
+    let env_file = handler.handle(&request("GET /.env HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    let git_config = handler.handle(&request("GET /.git/config HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(env_file.status_code(), "403");
+    assert!(!env_file.body().contains("SECRET"));
+    assert_eq!(git_config.status_code(), "403");
+  }
+
+  #[test]
+  fn test_hidden_paths_not_served_by_default() {
+    let dir = public_dir("hidden");
+    fs::write(format!("{}/.htaccess", dir), "Deny from all").unwrap();
+    let handler = StaticPageHandler::new(dir);
+
+    let htaccess = handler.handle(&request("GET /.htaccess HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    let env_file = handler.handle(&request("GET /.env HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    let git_config = handler.handle(&request("GET /.git/config HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(htaccess.status_code(), "403");
+    assert!(!htaccess.body().contains("Deny"));
+    assert_eq!(env_file.status_code(), "403");
+    assert_eq!(git_config.status_code(), "403");
+  }
+
+  #[test]
+  fn test_hidden_paths_hidden_with_not_found() {
+    let handler = StaticPageHandler::new(public_dir("hidden-404")).with_denied_status("404");
+
+    let response = handler.handle(&request("GET /.env HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(response.status_code(), "404");
+  }
+
+  #[test]
+  fn test_allowed_hidden_path_served() {
+    let dir = public_dir("allowed-hidden");
+    fs::write(format!("{}/.theme.css", dir), "body {}").unwrap();
+    let handler = StaticPageHandler::new(dir).with_allowed_hidden(vec![".theme.css".to_string()]);
+
+    let response = handler.handle(&request("GET /.theme.css HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(response.status_code(), "200");
+  }
+
+  #[test]
+  fn test_missing_file_not_found() {
+    let handler = StaticPageHandler::new(public_dir("missing")).with_denied(vec![".env".to_string()]);
+
+    let response = handler.handle(&request("GET /missing.html HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    assert_eq!(response.status_code(), "404");
+  }
+
+  #[test]
+  fn test_not_found_page_escapes_path() {
+    let response = PageNotFoundHandler.handle(&request(
+      "GET /<script>alert(1)</script> HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    ));
+
+    assert_eq!(response.status_code(), "404");
+    assert!(response.body().contains("/&lt;script&gt;alert(1)&lt;/script&gt;"));
+    assert!(!response.body().contains("<script>"));
+  }
+
+  #[test]
+  fn test_valid_order() {
+    assert_eq!(order(1, "21 Jan 2020", "Delivered").validate(), Ok(()));
+  }
+
+  #[test]
+  fn test_invalid_order_id() {
+    assert!(order(0, "21 Jan 2020", "Delivered").validate().is_err());
+    assert!(order(-4, "21 Jan 2020", "Delivered").validate().is_err());
+  }
+
+  #[test]
+  fn test_invalid_order_date() {
+    assert!(order(1, "  ", "Delivered").validate().is_err());
+  }
+
+  #[test]
+  fn test_invalid_order_status() {
+    assert!(order(1, "21 Jan 2020", "").validate().is_err());
+    assert!(order(1, "21 Jan 2020", "Lost").validate().is_err());
+  }
+
+  #[test]
+  fn test_create_invalid_order_rejected() {
+    let orders = Arc::new(Mutex::new(Vec::new()));
+    let handler = WebServiceHandler::new(Arc::clone(&orders), false);
+
+    let create = request(
+      "POST /api/shipping/orders HTTP/1.1\r\nHost: localhost\r\n\r\n\
+       {\"order_id\":-1,\"order_date\":\"3 Mar 2020\",\"order_status\":\"Pending\"}",
+    );
+    let response: String = handler.handle(&create).into();
+
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(response.contains("\"error\":\"invalid_order\""));
+    assert!(orders.lock().unwrap().is_empty());
+  }
+}
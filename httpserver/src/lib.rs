@@ -0,0 +1,14 @@
+pub mod logging;
+pub mod compress;
+pub mod config;
+pub mod connection;
+pub mod cors;
+pub mod csrf;
+pub mod handlers;
+pub mod metrics;
+pub mod mime;
+pub mod pool;
+pub mod router;
+pub mod server;
+pub mod session;
+pub mod template;
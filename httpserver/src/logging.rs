@@ -0,0 +1,135 @@
+//! Logger writing the events the server emits through the `log` crate to standard error.
+//!
+//! Applications may install any other `log` implementation instead, e.g. `env_logger`
+//! or a `tracing` subscriber bridged with `tracing-log`. Events are discarded until a
+//! logger is installed.
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+/// Represents a logger writing the events up to a maximum level to standard error.
+pub struct StderrLogger {
+  /// Least severe level that is written.
+  max_level: LevelFilter,
+}
+
+impl StderrLogger {
+  /// Creates a new [`StderrLogger`] object.
+  ///
+  /// # Arguments
+  ///
+  /// * `max_level`: Least severe level that is written.
+  pub fn new(max_level: LevelFilter) -> Self {
+    Self { max_level }
+  }
+
+  /// Creates a new [`StderrLogger`] object with the level named by the `LOG_LEVEL`
+  /// environment variable, defaulting to `info`.
+  pub fn from_env() -> Self {
+    let level = std::env::var("LOG_LEVEL").ok().and_then(|name| name.parse().ok());
+    Self::new(level.unwrap_or(LevelFilter::Info))
+  }
+
+  /// Installs this logger for the whole process, with its maximum level.
+  pub fn install(self) -> Result<(), SetLoggerError> {
+    let max_level = self.max_level;
+    log::set_logger(Box::leak(Box::new(self)))?;
+    log::set_max_level(max_level);
+    Ok(())
+  }
+}
+
+impl Log for StderrLogger {
+  fn enabled(&self, metadata: &Metadata) -> bool {
+    metadata.level() <= self.max_level
+  }
+
+  fn log(&self, record: &Record) {
+    if self.enabled(record.metadata()) {
+      eprintln!("{:<5} {}: {}", record.level(), record.target(), record.args());
+    }
+  }
+
+  fn flush(&self) {}
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use log::{debug, info, Level};
+  use std::{
+    sync::Mutex,
+    thread::{self, ThreadId},
+  };
+
+  /// Represents a logger keeping every event in memory, tagged with its thread.
+  pub struct CapturingLogger {
+    /// Events recorded so far.
+    events: Mutex<Vec<(ThreadId, Level, String)>>,
+  }
+
+  impl Log for CapturingLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+      true
+    }
+
+    fn log(&self, record: &Record) {
+      let event = (thread::current().id(), record.level(), record.args().to_string());
+      self.events.lock().unwrap().push(event);
+    }
+
+    fn flush(&self) {}
+  }
+
+  /// Capturing logger shared by the tests of the process.
+  static CAPTURE: CapturingLogger = CapturingLogger {
+    events: Mutex::new(Vec::new()),
+  };
+
+  /// Installs the capturing logger, if not done yet, and forgets the events of the
+  /// calling thread.
+  pub fn capture() {
+    if log::set_logger(&CAPTURE).is_ok() {
+      log::set_max_level(LevelFilter::Trace);
+    }
+    let current = thread::current().id();
+    CAPTURE.events.lock().unwrap().retain(|(id, _, _)| *id != current);
+  }
+
+  /// Gets the events the calling thread emitted since it called [`capture`].
+  pub fn captured() -> Vec<(Level, String)> {
+    let current = thread::current().id();
+    CAPTURE
+      .events
+      .lock()
+      .unwrap()
+      .iter()
+      .filter(|(id, _, _)| *id == current)
+      .map(|(_, level, message)| (*level, message.clone()))
+      .collect()
+  }
+
+  #[test]
+  fn test_stderr_logger_max_level() {
+    let logger = StderrLogger::new(LevelFilter::Info);
+    let metadata = |level| Metadata::builder().level(level).build();
+
+    assert!(logger.enabled(&metadata(Level::Warn)));
+    assert!(logger.enabled(&metadata(Level::Info)));
+    assert!(!logger.enabled(&metadata(Level::Debug)));
+  }
+
+  #[test]
+  fn test_events_captured_with_level() {
+    capture();
+    info!("connection from {}", "127.0.0.1");
+    debug!("routing");
+
+    assert_eq!(
+      captured(),
+      vec![
+        (Level::Info, "connection from 127.0.0.1".to_string()),
+        (Level::Debug, "routing".to_string())
+      ]
+    );
+  }
+}
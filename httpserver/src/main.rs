@@ -1,8 +1,7 @@
-mod handlers;
-mod server;
-mod router;
-use server::Server;
+use httpserver::{logging::StderrLogger, server::Server};
 fn main (){
-    let server = Server::new("localhost:3000");
+    let _ = StderrLogger::from_env().install();
+
+    let mut server = Server::new("localhost:3000");
     server.run();
-}
\ No newline at end of file
+}
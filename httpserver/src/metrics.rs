@@ -0,0 +1,110 @@
+use std::{
+  fmt::Write,
+  sync::atomic::{AtomicU64, Ordering},
+  time::Duration,
+};
+
+/// Upper bounds of the buckets request durations are counted in, by default.
+pub const DEFAULT_DURATION_BUCKETS: [Duration; 11] = [
+  Duration::from_millis(5),
+  Duration::from_millis(10),
+  Duration::from_millis(25),
+  Duration::from_millis(50),
+  Duration::from_millis(100),
+  Duration::from_millis(250),
+  Duration::from_millis(500),
+  Duration::from_secs(1),
+  Duration::from_millis(2500),
+  Duration::from_secs(5),
+  Duration::from_secs(10),
+];
+
+/// Represents a histogram of durations, counting them in buckets by upper bound, which
+/// can be recorded into while requests are served.
+#[derive(Debug)]
+pub struct Histogram {
+  /// Upper bounds of the buckets, in increasing order.
+  bounds: Vec<Duration>,
+  /// Number of durations within each bound but not the previous one, the last count
+  /// being for durations over every bound.
+  counts: Vec<AtomicU64>,
+  /// Sum of every duration, in nanoseconds.
+  sum: AtomicU64,
+}
+
+impl Histogram {
+  /// Creates a new [`Histogram`] object without any duration.
+  ///
+  /// # Arguments
+  ///
+  /// * `bounds`: Upper bounds of the buckets, in any order.
+  pub fn new(bounds: &[Duration]) -> Self {
+    let mut bounds = bounds.to_vec();
+    bounds.sort();
+    bounds.dedup();
+    Self {
+      counts: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+      bounds,
+      sum: AtomicU64::new(0),
+    }
+  }
+
+  /// Records the given duration in the bucket of the lowest bound it is within.
+  ///
+  /// # Arguments
+  ///
+  /// * `duration`: Duration to record.
+  pub fn observe(&self, duration: Duration) {
+    let bucket = self.bounds.partition_point(|bound| *bound < duration);
+    self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+    self.sum.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+  }
+
+  /// Serializes the histogram in the Prometheus text format, with cumulative buckets
+  /// labelled by their bound in seconds, followed by the sum and count of the durations.
+  ///
+  /// # Arguments
+  ///
+  /// * `name`: Name of the metric, e.g. `http_request_duration_seconds`.
+  /// * `help`: Description of the metric.
+  pub fn render(&self, name: &str, help: &str) -> String {
+    let mut text = format!("# HELP {} {}\n# TYPE {} histogram\n", name, help, name);
+
+    let mut count = 0;
+    for (index, bucket) in self.counts.iter().enumerate() {
+      count += bucket.load(Ordering::Relaxed);
+      let bound = match self.bounds.get(index) {
+        Some(bound) => bound.as_secs_f64().to_string(),
+        None => "+Inf".to_string(),
+      };
+      let _ = writeln!(text, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+    }
+
+    let sum = Duration::from_nanos(self.sum.load(Ordering::Relaxed));
+    let _ = writeln!(text, "{}_sum {}", name, sum.as_secs_f64());
+    let _ = writeln!(text, "{}_count {}", name, count);
+    text
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_histogram_counts_cumulative_buckets() {
+    let histogram = Histogram::new(&[Duration::from_millis(100), Duration::from_millis(10)]);
+    for millis in [5, 10, 50, 500] {
+      histogram.observe(Duration::from_millis(millis));
+    }
+
+    let text = histogram.render("duration_seconds", "Test durations.");
+
+    assert_eq!(
+      text,
+      "# HELP duration_seconds Test durations.\n# TYPE duration_seconds histogram\n\
+       duration_seconds_bucket{le=\"0.01\"} 2\nduration_seconds_bucket{le=\"0.1\"} 3\n\
+       duration_seconds_bucket{le=\"+Inf\"} 4\nduration_seconds_sum 0.565\nduration_seconds_count 4\n"
+    );
+  }
+}
@@ -0,0 +1,133 @@
+/// Content type used for files whose extension is not known.
+pub const DEFAULT_CONTENT_TYPE: &str = "text/html";
+
+/// Gets the content type of a file from its extension.
+///
+/// # Arguments
+///
+/// * `extension`: Extension of the file, without the leading dot.
+pub fn content_type(extension: Option<&str>) -> &'static str {
+  match extension.map(str::to_ascii_lowercase).as_deref() {
+    Some("html") | Some("htm") => "text/html",
+    Some("css") => "text/css",
+    Some("js") | Some("mjs") => "text/javascript",
+    Some("txt") => "text/plain",
+    Some("csv") => "text/csv",
+    Some("json") => "application/json",
+    Some("xml") => "application/xml",
+    Some("pdf") => "application/pdf",
+    Some("zip") => "application/zip",
+    Some("gz") => "application/gzip",
+    Some("wasm") => "application/wasm",
+    Some("svg") => "image/svg+xml",
+    Some("png") => "image/png",
+    Some("jpg") | Some("jpeg") => "image/jpeg",
+    Some("gif") => "image/gif",
+    Some("webp") => "image/webp",
+    Some("ico") => "image/x-icon",
+    Some("woff") => "font/woff",
+    Some("woff2") => "font/woff2",
+    _ => DEFAULT_CONTENT_TYPE,
+  }
+}
+
+/// Number of leading bytes inspected when sniffing the content type of a file.
+const SNIFF_LENGTH: usize = 512;
+
+/// Guesses the content type of a file from its first bytes, for files without an extension.
+///
+/// Well-known binary signatures are recognized first. Anything else is regarded as
+/// binary if it holds a NUL byte or is not valid UTF-8, as an HTML page if it starts
+/// with an HTML tag, and as plain text otherwise.
+///
+/// # Arguments
+///
+/// * `contents`: Contents of the file.
+pub fn sniff(contents: &[u8]) -> &'static str {
+  const SIGNATURES: [(&[u8], &str); 7] = [
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF8", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\0asm", "application/wasm"),
+  ];
+  if let Some((_, content_type)) = SIGNATURES.iter().find(|(magic, _)| contents.starts_with(magic)) {
+    return content_type;
+  }
+
+  let head = &contents[..contents.len().min(SNIFF_LENGTH)];
+  // A multi-byte character may be cut at the end of the inspected bytes
+  let text = match std::str::from_utf8(head) {
+    Ok(text) => text,
+    Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).unwrap_or(""),
+    Err(_) => return "application/octet-stream",
+  };
+  if text.contains('\0') {
+    return "application/octet-stream";
+  }
+
+  let start = text.trim_start().to_ascii_lowercase();
+  let is_html = ["<!doctype html", "<html", "<head", "<body", "<!--"]
+    .iter()
+    .any(|tag| start.starts_with(tag));
+  if is_html {
+    "text/html"
+  } else {
+    "text/plain"
+  }
+}
+
+/// Checks whether the given content type holds text, and thus takes a charset.
+///
+/// # Arguments
+///
+/// * `content_type`: Content type without parameters.
+pub fn is_text(content_type: &str) -> bool {
+  content_type.starts_with("text/")
+}
+
+/// Appends the charset parameter to text content types, leaving binary ones bare.
+///
+/// # Arguments
+///
+/// * `content_type`: Content type without parameters.
+/// * `charset`: Charset of text content (e.g. `utf-8`).
+pub fn with_charset(content_type: &str, charset: &str) -> String {
+  if is_text(content_type) {
+    format!("{}; charset={}", content_type, charset)
+  } else {
+    content_type.to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_content_type_from_extension() {
+    assert_eq!(content_type(Some("css")), "text/css");
+    assert_eq!(content_type(Some("PNG")), "image/png");
+    assert_eq!(content_type(Some("xml")), "application/xml");
+    assert_eq!(content_type(Some("unknown")), DEFAULT_CONTENT_TYPE);
+  }
+
+  #[test]
+  fn test_sniff_text_and_binary() {
+    assert_eq!(sniff(b"  <!DOCTYPE html><html></html>"), "text/html");
+    assert_eq!(sniff(b"MIT License\n\nCopyright"), "text/plain");
+    assert_eq!(sniff("caf\u{e9}".as_bytes()), "text/plain");
+    assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0"), "image/png");
+    assert_eq!(sniff(b"\x7fELF\x02\x01\0"), "application/octet-stream");
+    assert_eq!(sniff(b"\xfe\xfa\xfb"), "application/octet-stream");
+  }
+
+  #[test]
+  fn test_charset_for_text_types_only() {
+    assert_eq!(with_charset("text/html", "utf-8"), "text/html; charset=utf-8");
+    assert_eq!(with_charset("text/plain", "iso-8859-1"), "text/plain; charset=iso-8859-1");
+    assert_eq!(with_charset("image/png", "utf-8"), "image/png");
+  }
+}
@@ -0,0 +1,159 @@
+use std::{
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc::{self, Receiver, Sender},
+    Arc, Mutex,
+  },
+  thread::{self, JoinHandle},
+};
+
+/// Job run by a worker of the pool.
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Represents a fixed set of worker threads running jobs from a bounded queue.
+pub struct ThreadPool {
+  /// Sending side of the queue of jobs, dropped to stop the workers.
+  sender: Option<Sender<Job>>,
+  /// Threads running the jobs.
+  workers: Vec<JoinHandle<()>>,
+  /// Number of jobs accepted and not finished yet, whether queued or running.
+  pending: Arc<AtomicUsize>,
+  /// Maximum number of jobs waiting for a worker.
+  capacity: usize,
+}
+
+impl ThreadPool {
+  /// Creates a new [`ThreadPool`] object and starts its workers.
+  ///
+  /// # Arguments
+  ///
+  /// * `size`: Number of worker threads (at least one).
+  /// * `capacity`: Maximum number of jobs waiting for a worker once they are all busy.
+  pub fn new(size: usize, capacity: usize) -> Self {
+    let (sender, receiver) = mpsc::channel::<Job>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    let pending = Arc::new(AtomicUsize::new(0));
+
+    let workers = (0..size.max(1))
+      .map(|_| {
+        let receiver = Arc::clone(&receiver);
+        let pending = Arc::clone(&pending);
+        thread::spawn(move || Self::work(&receiver, &pending))
+      })
+      .collect();
+
+    Self {
+      sender: Some(sender),
+      workers,
+      pending,
+      capacity,
+    }
+  }
+
+  /// Runs the jobs of the queue until the pool is dropped.
+  ///
+  /// # Arguments
+  ///
+  /// * `receiver`: Receiving side of the queue of jobs, shared by the workers.
+  /// * `pending`: Number of jobs accepted and not finished yet.
+  fn work(receiver: &Mutex<Receiver<Job>>, pending: &AtomicUsize) {
+    loop {
+      // Release the queue while the job runs, so other workers can take the next ones
+      let job = match receiver.lock() {
+        Ok(receiver) => receiver.recv(),
+        Err(_) => return,
+      };
+      match job {
+        Ok(job) => {
+          job();
+          pending.fetch_sub(1, Ordering::SeqCst);
+        }
+        Err(_) => return,
+      }
+    }
+  }
+
+  /// Checks whether every worker is busy and the queue is full, so a new job is rejected.
+  pub fn is_saturated(&self) -> bool {
+    self.pending.load(Ordering::SeqCst) >= self.workers.len() + self.capacity
+  }
+
+  /// Queues the given job, unless every worker is busy and the queue is full.
+  ///
+  /// Returns the job back when it is rejected, so the caller can decline it.
+  ///
+  /// # Arguments
+  ///
+  /// * `job`: Function to run on a worker thread.
+  pub fn try_execute<F>(&self, job: F) -> Result<(), F>
+  where
+    F: FnOnce() + Send + 'static,
+  {
+    let limit = self.workers.len() + self.capacity;
+    let accepted = self
+      .pending
+      .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| (pending < limit).then_some(pending + 1))
+      .is_ok();
+    if !accepted {
+      return Err(job);
+    }
+
+    // The workers live as long as the pool, so the queue is always open here
+    if let Some(sender) = &self.sender {
+      let _ = sender.send(Box::new(job));
+    }
+    Ok(())
+  }
+}
+
+impl Drop for ThreadPool {
+  fn drop(&mut self) {
+    // Closing the queue stops the workers once the queued jobs are done
+    drop(self.sender.take());
+    for worker in self.workers.drain(..) {
+      let _ = worker.join();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::mpsc::sync_channel;
+
+  #[test]
+  fn test_jobs_run_on_workers() {
+    let (sender, receiver) = mpsc::channel();
+    let pool = ThreadPool::new(2, 4);
+
+    for i in 0..4 {
+      let sender = sender.clone();
+      assert!(pool.try_execute(move || sender.send(i).unwrap()).is_ok());
+    }
+    drop(pool);
+
+    let mut done: Vec<i32> = receiver.try_iter().collect();
+    done.sort();
+    assert_eq!(done, vec![0, 1, 2, 3]);
+  }
+
+  #[test]
+  fn test_overflow_rejected() {
+    let pool = ThreadPool::new(1, 1);
+    let (started, running) = sync_channel::<()>(0);
+    let (release, released) = sync_channel::<()>(0);
+
+    // Keep the only worker busy, then fill the queue
+    let busy = pool.try_execute(move || {
+      started.send(()).unwrap();
+      released.recv().unwrap();
+    });
+    assert!(busy.is_ok());
+    running.recv().unwrap();
+    assert!(pool.try_execute(|| {}).is_ok());
+    assert!(pool.is_saturated());
+    assert!(pool.try_execute(|| {}).is_err());
+
+    release.send(()).unwrap();
+  }
+}
@@ -1,51 +1,1723 @@
 use http::{
-    httprequest::{HttpRequest, Method, Resource},
-    httpresponse::HttpResponse,
-  };
-  
-  use std::io::prelude::*;
-  
-  use crate::handlers::{
-    Handler, PageNotFoundHandler, StaticPageHandler, WebServiceHandler,
-  };
-  
-  /// Represents a router to process requests.
-  pub struct Router;
-  
-  impl Router {
-    pub fn route(
-      request: HttpRequest,
-      stream: &mut impl Write,
-    ) -> () {
-      match request.method {
-        // Process GET requests
-        Method::GET => {
-          // Route according to the resource requested
-          match &request.resource {
-            Resource::Path(p) => {
-              let route: Vec<&str> = p.split("/").collect();
-  
-              match route[1] {
-                // Process a request to the API (/api)
-                "api" => {
-                  // Invoke the web service
-                  let response: HttpResponse = WebServiceHandler::handle(&request);
-                  let _ = response.send_response(stream);
-                }
-                // Process a requet to the page handler (/**)
-                _ => {
-                  let response: HttpResponse = StaticPageHandler::handle(&request);
-                  let _ = response.send_response(stream);
-                }
-              }
+  httprequest::{HttpRequest, Method, Resource, Version},
+  httpresponse::HttpResponse,
+};
+use log::{debug, warn};
+
+use std::{
+  collections::{hash_map::RandomState, HashMap},
+  fs,
+  hash::{BuildHasher, Hasher},
+  io,
+  path::Path,
+  sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, Mutex,
+  },
+  time::Instant,
+};
+
+use crate::compress;
+use crate::config::ServerConfig;
+use crate::cors::CorsPolicy;
+use crate::handlers::{
+  Handler, PageNotFoundHandler, StaticPageHandler, WebServiceHandler, DEFAULT_FAVICON,
+};
+use crate::metrics::Histogram;
+
+/// Path prefix of the API, whose errors are described in JSON.
+const API_PREFIX: &str = "/api";
+
+/// Path still served while the server is in maintenance mode.
+const HEALTH_PATH: &str = "/health";
+
+/// Path browsers request the icon of the site from.
+const FAVICON_PATH: &str = "/favicon.ico";
+
+/// Value of the `Cache-Control` header of the icon, which rarely changes.
+const FAVICON_CACHE_CONTROL: &str = "public, max-age=604800";
+
+/// Path answering with the parsed request, if enabled.
+const DEBUG_ECHO_PATH: &str = "/__debug/echo";
+
+/// Path answering with the metrics of the server, if enabled.
+const METRICS_PATH: &str = "/metrics";
+
+/// Methods the built-in handlers are dispatched for.
+const ROUTED_METHODS: [Method; 5] = [Method::GET, Method::HEAD, Method::POST, Method::PUT, Method::PATCH];
+
+/// Function producing the response to a request, registered as a route handler.
+pub type BoxedHandler = Box<dyn Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync>;
+
+/// Represents a route registered at runtime.
+struct Route {
+  /// HTTP method the route answers to.
+  method: Method,
+  /// Exact path the route answers to.
+  path: String,
+  /// Handler producing the response of the route.
+  handler: BoxedHandler,
+  /// Maximum length of the request bodies of the route, overriding the configured one.
+  max_body_size: Option<usize>,
+  /// Media type of the patches a `PATCH` route accepts, announced with `Accept-Patch`.
+  accept_patch: Option<String>,
+}
+
+/// Number of requests the router assigned an identifier to so far.
+static REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Represents a router to process requests.
+pub struct Router {
+  /// Configuration the routing decisions depend on.
+  config: ServerConfig,
+  /// Handler serving the API over the shared order store.
+  web_service: WebServiceHandler,
+  /// Handler serving the static files of the public directory.
+  static_pages: StaticPageHandler,
+  /// Routes registered at runtime, matched before the built-in handlers.
+  routes: Vec<Route>,
+  /// Directories served under the given path prefixes.
+  mounts: Vec<(String, StaticPageHandler)>,
+  /// CORS policies of the paths callable from other origins.
+  cors: Vec<(String, CorsPolicy)>,
+  /// Handlers used when no route matches a path under the given prefix.
+  prefix_fallbacks: Vec<(String, BoxedHandler)>,
+  /// Handler used when no route matches, unless a prefix fallback applies.
+  fallback: Option<BoxedHandler>,
+  /// Whether every request but the health check is answered with `503`.
+  maintenance: AtomicBool,
+  /// Contents of the icon served at `/favicon.ico`, kept in memory.
+  favicon: Vec<u8>,
+  /// Histogram of the time spent routing requests.
+  durations: Histogram,
+}
+
+impl Router {
+  /// Creates a new [`Router`] object.
+  ///
+  /// # Arguments
+  ///
+  /// * `config`: Configuration of the server owning the router.
+  pub fn new(config: ServerConfig) -> Self {
+    // Load the orders once, so requests do not hit the data file
+    let orders = Arc::new(Mutex::new(WebServiceHandler::load_json()));
+    let web_service = WebServiceHandler::new(orders, config.flush_orders);
+    let mut static_pages = StaticPageHandler::default()
+      .with_denied(config.denied_paths.clone())
+      .with_allowed_hidden(config.allowed_hidden_paths.clone())
+      .with_denied_status(config.denied_path_status)
+      .with_charset(config.charset)
+      .with_index(config.index_file.clone());
+    if let Some(robots_txt) = &config.robots_txt {
+      static_pages = static_pages.with_fallback_file("robots.txt", robots_txt.clone());
+    }
+    if let Some(default_root) = &config.default_root {
+      static_pages = static_pages.with_default_root(default_root.clone());
+    }
+    if let Some(sitemap_xml) = &config.sitemap_xml {
+      static_pages = static_pages.with_fallback_file("sitemap.xml", sitemap_xml.clone());
+    }
+    let favicon = match &config.favicon {
+      Some(path) => fs::read(path).unwrap_or_else(|e| {
+        warn!("Failed to read the icon {}, serving the default one: {}", path, e);
+        DEFAULT_FAVICON.to_vec()
+      }),
+      None => DEFAULT_FAVICON.to_vec(),
+    };
+
+    let durations = Histogram::new(&config.metrics_buckets);
+
+    Self {
+      config,
+      web_service,
+      static_pages,
+      routes: Vec::new(),
+      mounts: Vec::new(),
+      cors: Vec::new(),
+      prefix_fallbacks: Vec::new(),
+      fallback: None,
+      maintenance: AtomicBool::new(false),
+      favicon,
+      durations,
+    }
+  }
+
+  /// Turns the maintenance mode on or off, which can be done while requests are served.
+  ///
+  /// In maintenance mode, every request but the health check is answered with
+  /// `503 Service Unavailable` and the maintenance page.
+  ///
+  /// # Arguments
+  ///
+  /// * `enabled`: Whether the maintenance mode is on.
+  pub fn set_maintenance(&self, enabled: bool) {
+    self.maintenance.store(enabled, Ordering::Relaxed);
+  }
+
+  /// Checks whether the maintenance mode is on.
+  pub fn in_maintenance(&self) -> bool {
+    self.maintenance.load(Ordering::Relaxed)
+  }
+
+  /// Sets the handler used whenever no route matches the request.
+  ///
+  /// # Arguments
+  ///
+  /// * `handler`: Function producing the response for unmatched requests.
+  pub fn set_fallback<F>(&mut self, handler: F)
+  where
+    F: Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static,
+  {
+    self.fallback = Some(Box::new(handler));
+  }
+
+  /// Sets the handler used whenever no route matches a request under the given path prefix.
+  ///
+  /// When several prefixes apply, the longest one wins.
+  ///
+  /// # Arguments
+  ///
+  /// * `prefix`: Path prefix the fallback applies to (e.g. `/api`).
+  /// * `handler`: Function producing the response for unmatched requests.
+  pub fn set_prefix_fallback<F>(&mut self, prefix: &str, handler: F)
+  where
+    F: Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static,
+  {
+    let prefix = prefix.trim_end_matches('/').to_string();
+    self.prefix_fallbacks.retain(|(p, _)| *p != prefix);
+    self.prefix_fallbacks.push((prefix, Box::new(handler)));
+  }
+
+  /// Gets the method and path of the routes registered at runtime, in the order they
+  /// were registered, e.g. to log them at startup.
+  pub fn routes(&self) -> Vec<(Method, String)> {
+    self.routes.iter().map(|route| (route.method, route.path.clone())).collect()
+  }
+
+  /// Registers a closure as the handler of the given method and path.
+  ///
+  /// A `GET` route also answers `HEAD` requests without its body, unless a `HEAD` route
+  /// is registered for the same path.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method the route answers to.
+  /// * `path`: Exact path the route answers to (e.g. `/api/ping`).
+  /// * `handler`: Function producing the response of the route.
+  pub fn add_route<F>(&mut self, method: Method, path: &str, handler: F)
+  where
+    F: Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static,
+  {
+    self.routes.push(Route {
+      method,
+      path: path.to_string(),
+      handler: Box::new(handler),
+      max_body_size: None,
+      accept_patch: None,
+    });
+  }
+
+  /// Registers a closure as the `PATCH` handler of the given path, accepting patches of the
+  /// given media type, which `OPTIONS` requests to the path announce with `Accept-Patch`.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Exact path the route answers to (e.g. `/api/profile`).
+  /// * `accept_patch`: Media type of the accepted patches (e.g. `application/merge-patch+json`).
+  /// * `handler`: Function producing the response of the route.
+  pub fn add_patch_route<F>(&mut self, path: &str, accept_patch: &str, handler: F)
+  where
+    F: Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static,
+  {
+    self.add_route(Method::PATCH, path, handler);
+    if let Some(route) = self.routes.last_mut() {
+      route.accept_patch = Some(accept_patch.to_string());
+    }
+  }
+
+  /// Registers a closure as the handler of the given method and path, accepting request
+  /// bodies up to the given length instead of the configured one, e.g. for uploads.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method the route answers to.
+  /// * `path`: Exact path the route answers to (e.g. `/upload`).
+  /// * `max_body_size`: Maximum length of the request bodies, in bytes.
+  /// * `handler`: Function producing the response of the route.
+  pub fn add_route_with_max_body<F>(&mut self, method: Method, path: &str, max_body_size: usize, handler: F)
+  where
+    F: Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static,
+  {
+    self.add_route(method, path, handler);
+    if let Some(route) = self.routes.last_mut() {
+      route.max_body_size = Some(max_body_size);
+    }
+  }
+
+  /// Gets the maximum length of the body of a request to the given method and path: the
+  /// one of its route if set, the configured one otherwise.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method of the request.
+  /// * `path`: Path of the request, without query.
+  pub fn max_body_size(&self, method: Method, path: &str) -> usize {
+    self
+      .routes
+      .iter()
+      .find(|route| route.method == method && route.path == path)
+      .and_then(|route| route.max_body_size)
+      .unwrap_or(self.config.max_body_size)
+  }
+
+  /// Serves the files of the given directory under the given path prefix, e.g. the files
+  /// of `./assets` under `/static`.
+  ///
+  /// The files are served like the public directory, so no path under the prefix can
+  /// reach outside of the directory. When several prefixes apply, the longest one wins.
+  ///
+  /// # Arguments
+  ///
+  /// * `prefix`: Path prefix the directory is served under (e.g. `/static`).
+  /// * `directory`: Directory the files are served from.
+  pub fn mount_static(&mut self, prefix: &str, directory: &str) {
+    let prefix = prefix.trim_end_matches('/').to_string();
+    let handler = StaticPageHandler::new(directory.to_string())
+      .with_denied(self.config.denied_paths.clone())
+      .with_allowed_hidden(self.config.allowed_hidden_paths.clone())
+      .with_denied_status(self.config.denied_path_status)
+      .with_charset(self.config.charset)
+      .with_index(self.config.index_file.clone());
+    self.mounts.retain(|(p, _)| *p != prefix);
+    self.mounts.push((prefix, handler));
+  }
+
+  /// Sets the CORS policy of the given path, which answers the preflight requests to it
+  /// and allows the origins of the policy to read its responses.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Exact path the policy applies to (e.g. `/api/ping`).
+  /// * `policy`: CORS policy of the path.
+  pub fn set_cors(&mut self, path: &str, policy: CorsPolicy) {
+    self.cors.retain(|(p, _)| p != path);
+    self.cors.push((path.to_string(), policy));
+  }
+
+  /// Gets the CORS policy of the given path, if any.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Path of the request, without query.
+  fn cors_policy(&self, path: &str) -> Option<&CorsPolicy> {
+    self.cors.iter().find(|(p, _)| p == path).map(|(_, policy)| policy)
+  }
+
+  /// Checks that the directories the router serves files from exist, so a misconfigured
+  /// server fails at startup rather than answering `404` to every request.
+  ///
+  /// Returns a `NotFound` error naming the first missing directory. A missing data
+  /// directory is only an error if changes are written back to it: the API then starts
+  /// without orders.
+  pub fn check_directories(&self) -> io::Result<()> {
+    let missing = |kind: &str, path: &str| {
+      io::Error::new(io::ErrorKind::NotFound, format!("the {} directory {} does not exist", kind, path))
+    };
+
+    let public_path = self.static_pages.public_path();
+    if !Path::new(public_path).is_dir() {
+      return Err(missing("public", public_path));
+    }
+    for (prefix, handler) in &self.mounts {
+      if !Path::new(handler.public_path()).is_dir() {
+        return Err(missing(&format!("{} mounted", prefix), handler.public_path()));
+      }
+    }
+
+    let data_file = WebServiceHandler::data_file();
+    let data_path = Path::new(&data_file).parent().unwrap_or(Path::new("."));
+    if !data_path.is_dir() {
+      if self.config.flush_orders {
+        return Err(missing("data", &data_path.display().to_string()));
+      }
+      warn!("The data directory {} does not exist: serving no orders", data_path.display());
+    }
+    Ok(())
+  }
+
+  /// Gets the configuration the router was created with.
+  pub fn config(&self) -> &ServerConfig {
+    &self.config
+  }
+
+  /// Routes the given request to the appropiate handler and produces its response.
+  ///
+  /// The response carries the request identifier in its `X-Request-Id` header, and
+  /// the configured default headers the handler did not set. Its body is compressed
+  /// with gzip or deflate, as the client prefers, if it is compressible and long enough.
+  /// A `HEAD` request gets the headers of the `GET` response, without its body. The time
+  /// spent is recorded in the request duration histogram.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request to route.
+  pub fn route<'a>(&'a self, request: &'a HttpRequest) -> HttpResponse<'a> {
+    let started = Instant::now();
+    let mut response = self.dispatch(request);
+    if let Some(policy) = self.cors_policy(request.path()) {
+      policy.apply(request, &mut response);
+    }
+    self.config.apply_default_headers(&mut response);
+    compress::compress_response(
+      request,
+      &mut response,
+      self.config.compression_threshold,
+      &self.config.compressible_types,
+    );
+    response.set_header("X-Request-Id", &Self::request_id(request));
+    if request.method == Method::HEAD {
+      response.strip_body();
+    }
+    self.durations.observe(started.elapsed());
+    response
+  }
+
+  /// Gets the identifier of the given request, adopting the one sent by the client if valid.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request to identify.
+  fn request_id(request: &HttpRequest) -> String {
+    match request.header("X-Request-Id") {
+      // Only adopt short, header-safe identifiers
+      Some(id)
+        if !id.is_empty()
+          && id.len() <= 64
+          && id.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b)) =>
+      {
+        id.to_string()
+      }
+      _ => {
+        let counter = REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed) + 1;
+        let suffix = RandomState::new().build_hasher().finish() & 0xffff;
+        format!("{:x}-{:04x}", counter, suffix)
+      }
+    }
+  }
+
+  /// Dispatches the given request to the appropiate handler.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request to dispatch.
+  fn dispatch<'a>(&'a self, request: &'a HttpRequest) -> HttpResponse<'a> {
+    let Resource::Path(p) = &request.resource;
+    let path = request.path();
+
+    // Only answer the health check while in maintenance
+    if self.in_maintenance() && path != HEALTH_PATH {
+      debug!("Rejecting {:?} {} during maintenance", request.method, p);
+      return self.maintenance_page();
+    }
+
+    // A request line with a known method but an unknown version (e.g. HTTP/3.0) is
+    // well-formed, only in a version this server does not speak
+    if request.version == Version::UNINITIALIZED && request.method != Method::UNINITIALIZED {
+      debug!("Rejecting {:?} {} in an unsupported HTTP version", request.method, p);
+      return Self::error(path, "505", "version_not_supported", "the HTTP version is not supported");
+    }
+
+    // Simple requests of HTTP/0.9 are only served if allowed
+    if request.version == Version::V0_9 && !self.config.allow_http_0_9 {
+      return Self::error(path, "400", "bad_request", "the request line has no HTTP version");
+    }
+
+    // Only OPTIONS * may target something else than a path (e.g. GET * or GET abc)
+    let server_wide = request.method == Method::OPTIONS && p == "*";
+    if !p.starts_with('/') && !server_wide {
+      debug!("Rejecting the request target {:?}", p);
+      return Self::error(path, "400", "bad_request", "the request target is not a path");
+    }
+
+    // Reject long URIs before they reach the routing and the logs
+    let length = Self::decoded_length(p);
+    if length > self.config.max_uri_length {
+      debug!("Rejecting a URI of {} bytes", length);
+      return Self::error(path, "414", "uri_too_long", "the requested URI is too long");
+    }
+
+    // An HTTP/1.1 request that does not name its host is malformed
+    if request.version == Version::V1_1 && request.host().is_none_or(str::is_empty) {
+      return Self::error(path, "400", "bad_request", "the Host header is missing");
+    }
+
+    // Send clients of another host or scheme to the canonical one, except for the health
+    // check so that probes by address keep working
+    if let Some(origin) = self.config.canonical_origin.as_deref().filter(|_| path != HEALTH_PATH) {
+      if let Some(response) = Self::canonical_redirect(request, origin) {
+        return response;
+      }
+    }
+
+    // The only expectation understood is to continue sending the body
+    if let Some(expect) = request.header("Expect") {
+      if !expect.trim().eq_ignore_ascii_case("100-continue") {
+        debug!("Rejecting the unknown expectation {}", expect);
+        return Self::error(path, "417", "expectation_failed", &format!("the expectation {} is not supported", expect));
+      }
+    }
+
+    // Describe the capabilities of the whole server (OPTIONS *)
+    if request.method == Method::OPTIONS && path == "*" {
+      let allowed: Vec<String> = self.supported_methods().iter().map(|m| format!("{:?}", m)).collect();
+      let mut response = HttpResponse::new("200", Some(HashMap::new()), None);
+      response.set_header("Allow", &allowed.join(", "));
+      return response;
+    }
+
+    // Answer the preflight of a cross-origin request with the policy of its path
+    if let Some(policy) = self.cors_policy(path).filter(|_| CorsPolicy::is_preflight(request)) {
+      debug!("Answering the CORS preflight of {}", p);
+      let mut response = policy.preflight(request);
+      if let Some(accept_patch) = self.accept_patch(path) {
+        response.set_header("Accept-Patch", &accept_patch);
+      }
+      return response;
+    }
+
+    // Refuse the methods that are disabled by configuration
+    if self.is_disabled(&request.method) {
+      debug!("Rejecting disabled method {:?}", request.method);
+      return self.method_not_allowed(request, path, self.config.disabled_method_status);
+    }
+
+    // Process the routes registered at runtime first
+    if let Some(route) = self
+      .routes
+      .iter()
+      .find(|route| route.method == request.method && route.path == path)
+    {
+      debug!("Routing {:?} {} to a registered handler", request.method, p);
+      return (route.handler)(request);
+    }
+
+    // Answer HEAD like GET, without the body, unless a HEAD route is registered
+    if request.method == Method::HEAD {
+      if let Some(route) = self.routes.iter().find(|route| route.method == Method::GET && route.path == path) {
+        debug!("Routing {:?} {} to the registered GET handler", request.method, p);
+        return (route.handler)(request);
+      }
+    }
+
+    // Describe the methods a path answers to, unless a route answers OPTIONS itself
+    if request.method == Method::OPTIONS {
+      let mut allowed: Vec<String> = self.allowed_methods(path).iter().map(|m| format!("{:?}", m)).collect();
+      allowed.push(format!("{:?}", Method::OPTIONS));
+      let mut response = HttpResponse::new("204", Some(HashMap::new()), None);
+      response.set_header("Allow", &allowed.join(", "));
+      if let Some(accept_patch) = self.accept_patch(path) {
+        response.set_header("Accept-Patch", &accept_patch);
+      }
+      return response;
+    }
+
+    // A path with registered routes only answers to their methods
+    if self.routes.iter().any(|route| route.path == path) {
+      debug!("Rejecting {:?} {} for the methods of its routes", request.method, p);
+      return self.method_not_allowed(request, path, "405");
+    }
+
+    // Describe the request as parsed, to troubleshoot clients
+    if self.config.debug_echo && path == DEBUG_ECHO_PATH {
+      let body = serde_json::json!({
+        "method": format!("{:?}", request.method),
+        "path": path,
+        "query": request.query_string(),
+        "headers": request.headers,
+        "body": String::from_utf8_lossy(request.body_bytes()),
+      });
+      let mut headers: HashMap<&str, &str> = HashMap::new();
+      headers.insert("Content-Type", "application/json;charset=UTF-8");
+      return HttpResponse::new("200", Some(headers), Some(body.to_string()));
+    }
+
+    // Export the request durations for Prometheus to scrape
+    if self.config.enable_metrics && path == METRICS_PATH && matches!(request.method, Method::GET | Method::HEAD) {
+      let help = "Time spent routing requests, in seconds.";
+      let body = self.durations.render("http_request_duration_seconds", help);
+      let mut response = HttpResponse::new("200", Some(HashMap::new()), Some(body));
+      response.set_header("Content-Type", "text/plain; version=0.0.4");
+      return response;
+    }
+
+    // Serve the icon from memory, as browsers request it for every page
+    if path == FAVICON_PATH && matches!(request.method, Method::GET | Method::HEAD) {
+      let mut response = HttpResponse::with_bytes("200", Some(HashMap::new()), Some(self.favicon.clone()));
+      response.set_header("Content-Type", "image/x-icon");
+      response.set_header("Cache-Control", FAVICON_CACHE_CONTROL);
+      return response;
+    }
+
+    // Serve the files of the directory mounted under the longest matching prefix
+    let mount = self
+      .mounts
+      .iter()
+      .filter_map(|(prefix, handler)| {
+        let rest = path.strip_prefix(prefix.as_str())?;
+        match rest {
+          "" => Some((prefix, "/", handler)),
+          _ if rest.starts_with('/') => Some((prefix, rest, handler)),
+          _ => None,
+        }
+      })
+      .max_by_key(|(prefix, _, _)| prefix.len());
+    if let Some((prefix, rest, handler)) = mount {
+      if !matches!(request.method, Method::GET | Method::HEAD) {
+        return self.method_not_allowed(request, path, "405");
+      }
+      debug!("Routing {:?} {} to the directory mounted at {}", request.method, p, prefix);
+      let response = handler.serve(request, rest);
+      // A missing file means no route matched
+      return match response.status_code() {
+        "404" => self.not_found(request),
+        _ => response,
+      };
+    }
+
+    match request.method {
+      // Process GET and POST requests, HEAD being answered like GET
+      Method::GET | Method::HEAD | Method::POST => {
+        // Route according to the first segment of the resource requested
+        match path.split('/').nth(1) {
+          // Process a request to the API (/api)
+          Some("api") if self.web_service.serves(path) => {
+            debug!("Routing {:?} {} to the web service handler", request.method, p);
+            self.web_service.handle(request)
+          }
+          // Any other API path is not found
+          Some("api") => self.not_found(request),
+          // Process a requet to the page handler (/**)
+          _ if request.method != Method::POST => {
+            debug!("Routing {:?} {} to the static page handler", request.method, p);
+            let response = self.static_pages.handle(request);
+            // A missing file means no route matched
+            match response.status_code() {
+              "404" => self.not_found(request),
+              _ => response,
             }
           }
-        } // end match GET
-        // Any other method is regarded as not found
-        _ => {
-          let response: HttpResponse = PageNotFoundHandler::handle(&request);
-          let _ = response.send_response(stream);
+          // Static pages cannot be posted to
+          _ if self.serves_page(request) => self.method_not_allowed(request, path, "405"),
+          _ => self.not_found(request),
         }
+      } // end match GET and POST
+      // Process updates to the API
+      Method::PUT | Method::PATCH if self.web_service.serves(path) => {
+        debug!("Routing {:?} {} to the web service handler", request.method, p);
+        self.web_service.handle(request)
+      }
+      // Methods the parser does not recognize are regarded as a bad request
+      Method::UNINITIALIZED => Self::error(path, "400", "bad_request", "the method is not recognized"),
+      // Paths that exist only answer to the methods of their handler
+      _ if self.web_service.serves(path) || self.serves_page(request) => {
+        debug!("Rejecting {:?} {} for the methods of its handler", request.method, p);
+        self.method_not_allowed(request, path, "405")
+      }
+      // Recognized methods that are not routed anywhere are not implemented
+      ref method if !self.is_routed(method) => {
+        Self::error(path, "501", "not_implemented", &format!("method {:?} is not implemented", method))
+      }
+      // Any other method is regarded as not found
+      _ => self.not_found(request),
+    }
+  } // end fn dispatch()
+
+  /// Produces the permanent redirection of the given request to the canonical origin,
+  /// unless it was received with the canonical scheme and host already.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request to check.
+  /// * `origin`: Canonical scheme and host, e.g. `https://www.example.com`.
+  fn canonical_redirect<'a>(request: &HttpRequest, origin: &str) -> Option<HttpResponse<'a>> {
+    let (scheme, host) = origin.trim_end_matches('/').split_once("://")?;
+    let requested = request.host().filter(|host| !host.is_empty())?;
+    if request.scheme().eq_ignore_ascii_case(scheme) && requested.eq_ignore_ascii_case(host) {
+      return None;
+    }
+
+    let Resource::Path(p) = &request.resource;
+    debug!("Redirecting {}://{}{} to the canonical origin {}", request.scheme(), requested, p, origin);
+    Some(HttpResponse::redirect(&format!("{}://{}{}", scheme, host, p)).with_status(301))
+  }
+
+  /// Gets the length of the given URI once percent-decoded, in bytes.
+  ///
+  /// # Arguments
+  ///
+  /// * `uri`: Requested path and query.
+  fn decoded_length(uri: &str) -> usize {
+    let escapes = uri
+      .as_bytes()
+      .windows(3)
+      .filter(|w| w[0] == b'%' && w[1].is_ascii_hexdigit() && w[2].is_ascii_hexdigit())
+      .count();
+    uri.len() - 2 * escapes
+  }
+
+  /// Produces the response sent to requests while in maintenance.
+  fn maintenance_page(&self) -> HttpResponse<'_> {
+    let mut response = HttpResponse::new("503", None, PageNotFoundHandler::load_file("maintenance.html"));
+    response.set_retry_after(self.config.maintenance_retry_after);
+    response
+  }
+
+  /// Produces the response rejecting a method, listing the methods allowed for the path.
+  ///
+  /// The methods are listed in the `Allow` header and in a short body, which is JSON
+  /// if the client accepts it and HTML otherwise.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request that was rejected.
+  /// * `path`: Path of the request, without query.
+  /// * `status_code`: HTTP status numerical code for the response.
+  fn method_not_allowed<'a>(
+    &'a self,
+    request: &HttpRequest,
+    path: &str,
+    status_code: &'a str,
+  ) -> HttpResponse<'a> {
+    let allowed: Vec<String> = self.allowed_methods(path).iter().map(|m| format!("{:?}", m)).collect();
+    let wants_json = Self::is_api(path)
+      || request.is_ajax()
+      || request
+        .header("Accept")
+        .is_some_and(|accept| accept.to_ascii_lowercase().contains("application/json"));
+
+    let mut response = if wants_json {
+      let body = serde_json::json!({
+        "error": "method_not_allowed",
+        "message": format!("Method {:?} is not allowed for {}", request.method, path),
+        "allowed": allowed,
+      });
+      let mut headers: HashMap<&str, &str> = HashMap::new();
+      headers.insert("Content-Type", "application/json;charset=UTF-8");
+      HttpResponse::new(status_code, Some(headers), Some(body.to_string()))
+    } else {
+      let body = format!("<p>Allowed methods: {}</p>", allowed.join(", "));
+      HttpResponse::new(status_code, None, Some(body))
+    };
+    response.set_header("Allow", &allowed.join(", "));
+    response
+  }
+
+  /// Gets the methods the server answers to on at least one path.
+  fn supported_methods(&self) -> Vec<Method> {
+    let builtin = ROUTED_METHODS.into_iter().chain([Method::OPTIONS]);
+    let mut methods: Vec<Method> = Vec::new();
+    for method in builtin.chain(self.routes.iter().map(|route| route.method)) {
+      if !methods.contains(&method) {
+        methods.push(method);
+      }
+    }
+    methods.retain(|method| !self.is_disabled(method));
+    methods
+  }
+
+  /// Gets the methods the given path answers to, according to the routing table, `HEAD`
+  /// included wherever `GET` is.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Path of the request, without query.
+  fn allowed_methods(&self, path: &str) -> Vec<Method> {
+    let mut methods: Vec<Method> = Vec::new();
+    for route in self.routes.iter().filter(|route| route.path == path) {
+      if !methods.contains(&route.method) {
+        methods.push(route.method);
+      }
+    }
+
+    // Paths without registered routes are served by the built-in handlers
+    if methods.is_empty() {
+      methods = self.web_service.allowed_methods(path);
+    }
+    if methods.is_empty() {
+      methods.push(Method::GET);
+    }
+
+    // A HEAD request is answered with the headers of the GET response
+    if let Some(get) = methods.iter().position(|method| *method == Method::GET) {
+      if !methods.contains(&Method::HEAD) {
+        methods.insert(get + 1, Method::HEAD);
+      }
+    }
+    methods
+  }
+
+  /// Checks whether the path of the given request is served by the static page handler or
+  /// built in, whatever its method.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request to check.
+  fn serves_page(&self, request: &HttpRequest) -> bool {
+    let path = request.path();
+    path == FAVICON_PATH || self.static_pages.exists(request, path)
+  }
+
+  /// Gets the media types of the patches the `PATCH` routes of the given path accept, as
+  /// the value of the `Accept-Patch` header, if any declared one.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Path of the request, without query.
+  fn accept_patch(&self, path: &str) -> Option<String> {
+    let types: Vec<&str> = self
+      .routes
+      .iter()
+      .filter(|route| route.method == Method::PATCH && route.path == path)
+      .filter_map(|route| route.accept_patch.as_deref())
+      .collect();
+    (!types.is_empty()).then(|| types.join(", "))
+  }
+
+  /// Produces an error response, described in JSON for the API and with an empty
+  /// HTML body otherwise.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Path of the request, without query.
+  /// * `status_code`: HTTP status numerical code for the response.
+  /// * `error`: Machine-readable error code.
+  /// * `message`: Human-readable description of the error.
+  fn error<'a>(path: &str, status_code: &'a str, error: &str, message: &str) -> HttpResponse<'a> {
+    if Self::is_api(path) {
+      WebServiceHandler::error_response(status_code, error, message)
+    } else {
+      HttpResponse::new(status_code, None, None)
+    }
+  }
+
+  /// Checks whether the given path (without query) belongs to the API.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Path of the request.
+  fn is_api(path: &str) -> bool {
+    path.strip_prefix(API_PREFIX).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+  }
+
+  /// Produces the response for a request no route matched, using the configured fallbacks.
+  ///
+  /// Prefix fallbacks come first. API paths are then answered with a JSON error, and
+  /// any other path with the default fallback, if set, or the "404 Not Found" page.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request that was not matched.
+  fn not_found<'a>(&'a self, request: &'a HttpRequest) -> HttpResponse<'a> {
+    let Resource::Path(p) = &request.resource;
+    let path = request.path();
+
+    let prefix_fallback = self
+      .prefix_fallbacks
+      .iter()
+      .filter(|(prefix, _)| {
+        path == prefix || path.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.starts_with('/'))
+      })
+      .max_by_key(|(prefix, _)| prefix.len());
+
+    match (prefix_fallback, &self.fallback) {
+      (Some((prefix, handler)), _) => {
+        debug!("Routing {:?} {} to the fallback of {}", request.method, p, prefix);
+        handler(request)
+      }
+      (None, _) if Self::is_api(path) => {
+        debug!("Routing {:?} {} to the API not found error", request.method, p);
+        WebServiceHandler::error_response("404", "not_found", &format!("{} does not exist", path))
+      }
+      (None, Some(handler)) => {
+        debug!("Routing {:?} {} to the fallback handler", request.method, p);
+        handler(request)
+      }
+      (None, None) => {
+        debug!("Routing {:?} {} to the not found handler", request.method, p);
+        PageNotFoundHandler.handle(request)
       }
-    } // end fn route()
-  }
\ No newline at end of file
+    }
+  }
+
+  /// Checks whether the given method is dispatched to at least one handler.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method of the request.
+  fn is_routed(&self, method: &Method) -> bool {
+    ROUTED_METHODS.contains(method) || self.routes.iter().any(|route| route.method == *method)
+  }
+
+  /// Checks whether the given method is security-sensitive and disabled.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method of the request.
+  fn is_disabled(&self, method: &Method) -> bool {
+    match method {
+      Method::TRACE => !self.config.enable_trace,
+      Method::CONNECT => !self.config.enable_connect,
+      _ => false,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::Duration;
+
+  fn route_to_string(router: &Router, raw: &str) -> String {
+    let request: HttpRequest = raw.to_string().into();
+    router.route(&request).into()
+  }
+
+  #[test]
+  fn test_trace_rejected_by_default() {
+    let router = Router::new(ServerConfig::default());
+    let response = route_to_string(&router, "TRACE /x HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    assert!(response.contains("Allow:GET, HEAD\r\n"));
+  }
+
+  #[test]
+  fn test_connect_rejected_by_default() {
+    let router = Router::new(ServerConfig::default());
+    let response = route_to_string(&router, "CONNECT /x HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+  }
+
+  #[test]
+  fn test_trace_rejected_with_configured_status() {
+    let config = ServerConfig {
+      disabled_method_status: "501",
+      ..ServerConfig::default()
+    };
+    let router = Router::new(config);
+    let response = route_to_string(&router, "TRACE /x HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 501 "));
+  }
+
+  #[test]
+  fn test_trace_allowed_when_enabled() {
+    let config = ServerConfig {
+      enable_trace: true,
+      ..ServerConfig::default()
+    };
+    let router = Router::new(config);
+    let response = route_to_string(&router, "TRACE /x HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 501 Not Implemented\r\n"));
+  }
+
+  #[test]
+  fn test_head_on_builtin_resources() {
+    let router = Router::new(ServerConfig::default());
+
+    let raw = |method: &str, path: &str| format!("{} {} HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: 1\r\n\r\n", method, path);
+
+    for path in ["/health", "/api/shipping/orders"] {
+      let get = route_to_string(&router, &raw("GET", path));
+      let head = route_to_string(&router, &raw("HEAD", path));
+      let (get_head, _) = get.split_once("\r\n\r\n").unwrap();
+
+      assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", path);
+      assert_eq!(head, format!("{}\r\n\r\n", get_head), "{}", path);
+    }
+  }
+
+  #[test]
+  fn test_unrouted_method_not_implemented() {
+    let router = Router::new(ServerConfig::default());
+    let response = route_to_string(&router, "DELETE /x HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 501 Not Implemented\r\n"));
+  }
+
+  #[test]
+  fn test_unrouted_method_on_existing_path_not_allowed() {
+    let router = Router::new(ServerConfig::default());
+    let api: HttpRequest = "DELETE /api/shipping/orders HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    let page: HttpRequest = "DELETE /health HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+
+    let api = router.route(&api);
+    let page = router.route(&page);
+
+    assert_eq!(api.status_code(), "405");
+    assert_eq!(api.header("Allow"), Some("GET, HEAD, POST"));
+    assert_eq!(page.status_code(), "405");
+    assert_eq!(page.header("Allow"), Some("GET, HEAD"));
+  }
+
+  #[test]
+  fn test_target_without_path_rejected() {
+    let router = Router::new(ServerConfig::default());
+    let asterisk = route_to_string(&router, "GET * HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let relative = route_to_string(&router, "GET abc HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert!(asterisk.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(relative.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+  }
+
+  #[test]
+  fn test_long_uri_rejected() {
+    let router = Router::new(ServerConfig { max_uri_length: 64, ..ServerConfig::default() });
+    let long = route_to_string(&router, &format!("GET /{} HTTP/1.1\r\nHost: localhost\r\n\r\n", "a".repeat(64)));
+    let escaped = route_to_string(&router, &format!("GET /health?q={} HTTP/1.1\r\nHost: localhost\r\n\r\n", "%41".repeat(20)));
+
+    assert!(long.starts_with("HTTP/1.1 414 URI Too Long\r\n"));
+    assert!(!escaped.starts_with("HTTP/1.1 414 URI Too Long\r\n"));
+  }
+
+  #[test]
+  fn test_missing_host_bad_request() {
+    let router = Router::new(ServerConfig::default());
+    let response = route_to_string(&router, "GET /health HTTP/1.1\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+  }
+
+  #[test]
+  fn test_empty_host_bad_request() {
+    let router = Router::new(ServerConfig::default());
+    let response = route_to_string(&router, "GET /health HTTP/1.1\r\nHost:\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+  }
+
+  #[test]
+  fn test_missing_host_allowed_for_http_1_0() {
+    let router = Router::new(ServerConfig::default());
+    let response = route_to_string(&router, "GET /health HTTP/1.0\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+  }
+
+  #[test]
+  fn test_lowercase_host_accepted() {
+    let router = Router::new(ServerConfig::default());
+    let response = route_to_string(&router, "GET /health HTTP/1.1\r\nhost: localhost\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+  }
+
+  #[test]
+  fn test_unknown_expectation_failed() {
+    let router = Router::new(ServerConfig::default());
+    let response = route_to_string(&router, "GET /health HTTP/1.1\r\nHost: localhost\r\nExpect: foo\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 417 Expectation Failed\r\n"));
+  }
+
+  #[test]
+  fn test_continue_expectation_accepted() {
+    let router = Router::new(ServerConfig::default());
+    let response = route_to_string(&router, "GET /health HTTP/1.1\r\nHost: localhost\r\nExpect: 100-Continue\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+  }
+
+  #[test]
+  fn test_incoming_request_id_echoed() {
+    let router = Router::new(ServerConfig::default());
+    let request: HttpRequest =
+      "GET /health HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: abc-123\r\n\r\n".into();
+
+    assert_eq!(router.route(&request).header("X-Request-Id"), Some("abc-123"));
+  }
+
+  #[test]
+  fn test_missing_request_id_generated() {
+    let router = Router::new(ServerConfig::default());
+    let request: HttpRequest = "GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+
+    let first = router.route(&request).header("X-Request-Id").unwrap().to_string();
+    let second = router.route(&request).header("X-Request-Id").unwrap().to_string();
+
+    assert!(!first.is_empty());
+    assert_ne!(first, second);
+  }
+
+  #[test]
+  fn test_unsafe_request_id_replaced() {
+    let router = Router::new(ServerConfig::default());
+    let request: HttpRequest =
+      "GET /health HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: a b\r\n\r\n".into();
+
+    assert_ne!(router.route(&request).header("X-Request-Id"), Some("a b"));
+  }
+
+  #[test]
+  fn test_closure_route_dispatched() {
+    let mut router = Router::new(ServerConfig::default());
+    router.add_route(Method::GET, "/api/ping", |_request| {
+      HttpResponse::new("200", None, Some("pong".to_string()))
+    });
+
+    let request: HttpRequest = "GET /api/ping?x=1 HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    let response = router.route(&request);
+
+    assert_eq!(response.status_code(), "200");
+    assert_eq!(response.body(), "pong");
+  }
+
+  #[test]
+  fn test_closure_route_enables_method() {
+    let mut router = Router::new(ServerConfig::default());
+    router.add_route(Method::DELETE, "/api/cache", |_request| HttpResponse::new("200", None, None));
+
+    let registered: HttpRequest = "DELETE /api/cache HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    let other: HttpRequest = "DELETE /api/other HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+
+    assert_eq!(router.route(&registered).status_code(), "200");
+    assert_eq!(router.route(&other).status_code(), "404");
+  }
+
+  #[test]
+  fn test_method_not_allowed_for_route_path() {
+    let mut router = Router::new(ServerConfig::default());
+    router.add_route(Method::GET, "/ping", |_request| HttpResponse::new("200", None, None));
+
+    let response = route_to_string(&router, "POST /ping HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    assert!(response.contains("Allow:GET, HEAD\r\n"));
+    assert!(response.ends_with("<p>Allowed methods: GET, HEAD</p>"));
+  }
+
+  #[test]
+  fn test_debug_echo_reflects_request() {
+    let router = Router::new(ServerConfig { debug_echo: true, ..ServerConfig::default() });
+    let request: HttpRequest =
+      "POST /__debug/echo?x=1 HTTP/1.1\r\nHost: localhost:3000\r\nX-Trace: a:b\r\n\r\nhello".into();
+
+    let response = router.route(&request);
+    let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+
+    assert_eq!(response.status_code(), "200");
+    assert_eq!(
+      body,
+      serde_json::json!({
+        "method": "POST",
+        "path": "/__debug/echo",
+        "query": "x=1",
+        "headers": {"Host": "localhost:3000", "X-Trace": "a:b"},
+        "body": "hello",
+      })
+    );
+  }
+
+  #[test]
+  fn test_metrics_histogram_counts_requests() {
+    let router = Router::new(ServerConfig {
+      enable_metrics: true,
+      metrics_buckets: vec![Duration::ZERO, Duration::from_secs(60)],
+      ..ServerConfig::default()
+    });
+    for _ in 0..3 {
+      router.route(&"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n".into());
+    }
+
+    let response = route_to_string(&router, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("# TYPE http_request_duration_seconds histogram\n"));
+    assert!(response.contains("http_request_duration_seconds_bucket{le=\"0\"} 0\n"));
+    assert!(response.contains("http_request_duration_seconds_bucket{le=\"60\"} 3\n"));
+    assert!(response.contains("http_request_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+    assert!(response.contains("http_request_duration_seconds_count 3\n"));
+  }
+
+  #[test]
+  fn test_options_announces_accept_patch() {
+    let mut router = Router::new(ServerConfig::default());
+    let ok = |_request: &HttpRequest| HttpResponse::new("200", None, None);
+    router.add_route(Method::GET, "/api/profile", ok);
+    router.add_patch_route("/api/profile", "application/json", ok);
+    router.add_route(Method::GET, "/api/ping", ok);
+
+    let profile = HttpRequest::new(Method::OPTIONS, "/api/profile").with_header("Host", "localhost");
+    let ping = HttpRequest::new(Method::OPTIONS, "/api/ping").with_header("Host", "localhost");
+    let profile = router.route(&profile);
+    let ping = router.route(&ping);
+
+    assert_eq!(profile.status_code(), "204");
+    assert_eq!(profile.header("Allow"), Some("GET, HEAD, PATCH, OPTIONS"));
+    assert_eq!(profile.header("Accept-Patch"), Some("application/json"));
+    assert_eq!(ping.header("Allow"), Some("GET, HEAD, OPTIONS"));
+    assert_eq!(ping.header("Accept-Patch"), None);
+  }
+
+  #[test]
+  fn test_options_lists_head_for_builtin_paths() {
+    let router = Router::new(ServerConfig::default());
+    let orders = HttpRequest::new(Method::OPTIONS, "/api/shipping/orders").with_header("Host", "localhost");
+    let order = HttpRequest::new(Method::OPTIONS, "/api/shipping/orders/1").with_header("Host", "localhost");
+    let health = HttpRequest::new(Method::OPTIONS, "/health").with_header("Host", "localhost");
+
+    assert_eq!(router.route(&orders).header("Allow"), Some("GET, HEAD, POST, OPTIONS"));
+    assert_eq!(router.route(&order).header("Allow"), Some("GET, HEAD, PUT, PATCH, OPTIONS"));
+    assert_eq!(router.route(&health).header("Allow"), Some("GET, HEAD, OPTIONS"));
+  }
+
+  #[test]
+  fn test_debug_echo_disabled_by_default() {
+    let router = Router::new(ServerConfig::default());
+    let response = route_to_string(&router, "GET /__debug/echo HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+  }
+
+  #[test]
+  fn test_check_directories() {
+    let mut router = Router::new(ServerConfig::default());
+    assert!(router.check_directories().is_ok());
+
+    router.static_pages = StaticPageHandler::new("/nonexistent/public".to_string());
+    let error = router.check_directories().unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    assert_eq!(error.to_string(), "the public directory /nonexistent/public does not exist");
+  }
+
+  #[test]
+  fn test_check_mounted_directories() {
+    let mut router = Router::new(ServerConfig::default());
+    router.mount_static("/assets", "/nonexistent/assets");
+
+    let error = router.check_directories().unwrap_err();
+    assert_eq!(error.to_string(), "the /assets mounted directory /nonexistent/assets does not exist");
+  }
+
+  #[test]
+  fn test_routes_listed() {
+    let mut router = Router::new(ServerConfig::default());
+    router.add_route(Method::GET, "/api/ping", |_request| HttpResponse::new("200", None, None));
+    router.add_route(Method::DELETE, "/api/cache", |_request| HttpResponse::new("200", None, None));
+    router.add_route(Method::PUT, "/api/ping", |_request| HttpResponse::new("200", None, None));
+
+    assert_eq!(
+      router.routes(),
+      vec![
+        (Method::GET, "/api/ping".to_string()),
+        (Method::DELETE, "/api/cache".to_string()),
+        (Method::PUT, "/api/ping".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_head_answered_by_get_route() {
+    let mut router = Router::new(ServerConfig::default());
+    router.add_route(Method::GET, "/ping", |_request| {
+      let mut response = HttpResponse::new("200", None, Some("pong".to_string()));
+      response.set_header("X-Pong", "1");
+      response
+    });
+
+    let get = route_to_string(&router, "GET /ping HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: 1\r\n\r\n");
+    let head = route_to_string(&router, "HEAD /ping HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: 1\r\n\r\n");
+
+    assert!(get.ends_with("\r\n\r\npong"));
+    assert_eq!(head, get.trim_end_matches("pong"));
+  }
+
+  #[test]
+  fn test_head_route_overrides_get_route() {
+    let mut router = Router::new(ServerConfig::default());
+    router.add_route(Method::GET, "/ping", |_request| HttpResponse::new("200", None, Some("pong".to_string())));
+    router.add_route(Method::HEAD, "/ping", |_request| HttpResponse::new("204", Some(HashMap::new()), None));
+
+    let response = route_to_string(&router, "HEAD /ping HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"));
+  }
+
+  #[test]
+  fn test_cors_preflight_per_route() {
+    let mut router = Router::new(ServerConfig::default());
+    router.add_route(Method::GET, "/api/read", |_request| HttpResponse::new("200", None, None));
+    router.add_route(Method::DELETE, "/api/write", |_request| HttpResponse::new("200", None, None));
+    router.set_cors("/api/read", CorsPolicy { allowed_methods: vec![Method::GET], ..CorsPolicy::default() });
+    router.set_cors(
+      "/api/write",
+      CorsPolicy {
+        allowed_methods: vec![Method::GET, Method::DELETE],
+        allowed_headers: vec!["Authorization".to_string()],
+        ..CorsPolicy::default()
+      },
+    );
+    let preflight = |path: &str, method: &str| {
+      let raw = format!(
+        "OPTIONS {} HTTP/1.1\r\nHost: localhost\r\nOrigin: https://example.com\r\nAccess-Control-Request-Method: {}\r\n\r\n",
+        path, method
+      );
+      let request: HttpRequest = raw.into();
+      let response = router.route(&request);
+      let header = |name: &str| response.header(name).map(str::to_string);
+      (header("Access-Control-Allow-Methods"), header("Access-Control-Allow-Headers"))
+    };
+
+    assert_eq!(preflight("/api/read", "GET"), (Some("GET".to_string()), None));
+    assert_eq!(preflight("/api/read", "DELETE"), (None, None));
+    assert_eq!(
+      preflight("/api/write", "DELETE"),
+      (Some("GET, DELETE".to_string()), Some("Authorization".to_string()))
+    );
+  }
+
+  #[test]
+  fn test_cors_origin_on_actual_response() {
+    let mut router = Router::new(ServerConfig::default());
+    router.add_route(Method::GET, "/api/read", |_request| HttpResponse::new("200", None, None));
+    router.set_cors("/api/read", CorsPolicy::default());
+
+    let request: HttpRequest = "GET /api/read HTTP/1.1\r\nHost: localhost\r\nOrigin: https://example.com\r\n\r\n".into();
+    let unrelated: HttpRequest = "GET /health HTTP/1.1\r\nHost: localhost\r\nOrigin: https://example.com\r\n\r\n".into();
+
+    assert_eq!(router.route(&request).header("Access-Control-Allow-Origin"), Some("*"));
+    assert_eq!(router.route(&unrelated).header("Access-Control-Allow-Origin"), None);
+  }
+
+  #[test]
+  fn test_method_not_allowed_body_negotiated() {
+    let mut router = Router::new(ServerConfig::default());
+    router.add_route(Method::GET, "/api/ping", |_request| HttpResponse::new("200", None, None));
+    router.add_route(Method::PUT, "/api/ping", |_request| HttpResponse::new("200", None, None));
+
+    let request: HttpRequest =
+      "DELETE /api/ping HTTP/1.1\r\nHost: localhost\r\nAccept: application/json\r\n\r\n".into();
+    let response = router.route(&request);
+    let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+
+    assert_eq!(response.status_code(), "405");
+    assert_eq!(response.header("Allow"), Some("GET, HEAD, PUT"));
+    assert_eq!(body["allowed"], serde_json::json!(["GET", "HEAD", "PUT"]));
+  }
+
+  #[test]
+  fn test_method_not_allowed_json_for_ajax() {
+    let mut router = Router::new(ServerConfig::default());
+    router.add_route(Method::GET, "/ping", |_request| HttpResponse::new("200", None, None));
+
+    let request: HttpRequest =
+      "POST /ping HTTP/1.1\r\nHost: localhost\r\nX-Requested-With: XMLHttpRequest\r\n\r\n".into();
+    let response = router.route(&request);
+    let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+
+    assert_eq!(body["allowed"], serde_json::json!(["GET", "HEAD"]));
+  }
+
+  #[test]
+  fn test_disabled_method_lists_builtin_methods() {
+    let router = Router::new(ServerConfig::default());
+    let request: HttpRequest = "TRACE /api/shipping/orders HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+
+    assert_eq!(router.route(&request).header("Allow"), Some("GET, HEAD, POST"));
+  }
+
+  #[test]
+  fn test_custom_fallback_for_unmatched_path() {
+    let mut router = Router::new(ServerConfig::default());
+    router.set_fallback(|_request| HttpResponse::new("200", None, Some("spa".to_string())));
+
+    let request: HttpRequest = "GET /app/settings HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    let matched: HttpRequest = "GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+
+    assert_eq!(router.route(&request).body(), "spa");
+    assert_ne!(router.route(&matched).body(), "spa");
+  }
+
+  #[test]
+  fn test_prefix_fallback_for_unmatched_api_path() {
+    let mut router = Router::new(ServerConfig::default());
+    router.set_fallback(|_request| HttpResponse::new("404", None, Some("<h1>html</h1>".to_string())));
+    router.set_prefix_fallback("/api", |_request| {
+      HttpResponse::new("404", None, Some("{\"error\":\"not_found\"}".to_string()))
+    });
+
+    let api: HttpRequest = "GET /api/unknown HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    let page: HttpRequest = "GET /apiary HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+
+    assert_eq!(router.route(&api).body(), "{\"error\":\"not_found\"}");
+    assert_eq!(router.route(&page).body(), "<h1>html</h1>");
+  }
+
+  #[test]
+  fn test_maintenance_mode_toggled() {
+    let router = Router::new(ServerConfig::default());
+    let page: HttpRequest = "GET /index.html HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    let api: HttpRequest = "POST /api/shipping/orders HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+
+    router.set_maintenance(true);
+    let response = router.route(&page);
+    assert!(router.in_maintenance());
+    assert_eq!(response.status_code(), "503");
+    assert_eq!(response.header("Retry-After"), Some("120"));
+    assert!(response.body().contains("maintenance"));
+    assert_eq!(router.route(&api).status_code(), "503");
+
+    router.set_maintenance(false);
+    assert_eq!(router.route(&page).status_code(), "200");
+  }
+
+  #[test]
+  fn test_health_served_during_maintenance() {
+    let router = Router::new(ServerConfig::default());
+    router.set_maintenance(true);
+
+    let response = route_to_string(&router, "GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let unhealthy = route_to_string(&router, "GET /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(unhealthy.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+  }
+
+  #[test]
+  fn test_unknown_api_path_json_error() {
+    let router = Router::new(ServerConfig::default());
+    let request: HttpRequest = "GET /api/unknown HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    let response = router.route(&request);
+    let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+
+    assert_eq!(response.status_code(), "404");
+    assert_eq!(response.header("Content-Type"), Some("application/json;charset=UTF-8"));
+    assert_eq!(body["error"], "not_found");
+    assert_eq!(body["message"], "/api/unknown does not exist");
+  }
+
+  #[test]
+  fn test_api_errors_json_page_errors_html() {
+    let router = Router::new(ServerConfig::default());
+    let api: HttpRequest = "DELETE /api/shipping/orders HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    let unknown: HttpRequest = "DELETE /api/unknown HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    let page: HttpRequest = "GET /unknown HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    let apiary: HttpRequest = "GET /apiary HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+
+    let api = router.route(&api);
+    let unknown = router.route(&unknown);
+    assert_eq!(api.status_code(), "405");
+    assert!(api.body().contains("\"error\":\"method_not_allowed\""));
+    assert_eq!(unknown.status_code(), "501");
+    assert!(unknown.body().contains("\"error\":\"not_implemented\""));
+    assert_eq!(router.route(&page).header("Content-Type"), Some("text/html"));
+    assert_eq!(router.route(&apiary).header("Content-Type"), Some("text/html"));
+  }
+
+  /// Configuration adding the usual security headers to every response.
+  fn secure_config() -> ServerConfig {
+    let default_headers = [
+      ("X-Content-Type-Options", "nosniff"),
+      ("X-Frame-Options", "DENY"),
+      ("Referrer-Policy", "no-referrer"),
+    ];
+    ServerConfig {
+      default_headers: default_headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+      ..ServerConfig::default()
+    }
+  }
+
+  #[test]
+  fn test_default_headers_on_every_response() {
+    let router = Router::new(secure_config());
+    let page = route_to_string(&router, "GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let api = route_to_string(&router, "GET /api/shipping/orders HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    for response in [page, api] {
+      assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+      assert!(response.contains("X-Content-Type-Options:nosniff\r\n"));
+      assert!(response.contains("X-Frame-Options:DENY\r\n"));
+      assert!(response.contains("Referrer-Policy:no-referrer\r\n"));
+    }
+  }
+
+  #[test]
+  fn test_handler_header_wins_over_default() {
+    let mut router = Router::new(secure_config());
+    router.add_route(Method::GET, "/embed", |_request| {
+      let mut response = HttpResponse::new("200", None, None);
+      response.set_header("x-frame-options", "SAMEORIGIN");
+      response
+    });
+
+    let request: HttpRequest = "GET /embed HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    let response = router.route(&request);
+
+    assert_eq!(response.header("X-Frame-Options"), Some("SAMEORIGIN"));
+    assert_eq!(response.header("Referrer-Policy"), Some("no-referrer"));
+  }
+
+  #[test]
+  fn test_options_asterisk_lists_capabilities() {
+    let mut router = Router::new(ServerConfig::default());
+    router.add_route(Method::DELETE, "/api/cache", |_request| HttpResponse::new("200", None, None));
+
+    let request: HttpRequest = "OPTIONS * HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    let response = router.route(&request);
+    let http = String::from(response.clone());
+
+    assert_eq!(response.status_code(), "200");
+    assert_eq!(response.header("Allow"), Some("GET, HEAD, POST, PUT, PATCH, OPTIONS, DELETE"));
+    assert_eq!(response.header("Content-Type"), None);
+    assert!(response.body_bytes().is_empty());
+    assert!(http.ends_with("Content-Length: 0\r\n\r\n"));
+  }
+
+  #[test]
+  fn test_default_robots_served() {
+    let router = Router::new(ServerConfig::default());
+    let without = Router::new(ServerConfig {
+      robots_txt: None,
+      ..ServerConfig::default()
+    });
+    let request: HttpRequest = "GET /robots.txt HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+
+    assert_eq!(router.route(&request).body(), "User-agent: *\nAllow: /\n");
+    assert_eq!(without.route(&request).status_code(), "404");
+  }
+
+  #[test]
+  fn test_request_without_version_rejected_by_default() {
+    let router = Router::new(ServerConfig::default());
+    let response = route_to_string(&router, "GET /health\r\n\r\n");
+    let empty = route_to_string(&router, "\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(empty.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+  }
+
+  #[test]
+  fn test_non_canonical_host_redirected() {
+    let router = Router::new(ServerConfig {
+      canonical_origin: Some("https://www.example.com".to_string()),
+      trust_proxy: true,
+      ..ServerConfig::default()
+    });
+    let mut request: HttpRequest = "GET /orders?page=2 HTTP/1.1\r\nHost: example.com\r\n\r\n".into();
+    request.trust_proxy = true;
+
+    let response = router.route(&request);
+
+    assert_eq!(response.status_code(), "301");
+    assert_eq!(response.header("Location"), Some("https://www.example.com/orders?page=2"));
+  }
+
+  #[test]
+  fn test_canonical_host_passes_through() {
+    let router = Router::new(ServerConfig {
+      canonical_origin: Some("https://www.example.com".to_string()),
+      trust_proxy: true,
+      ..ServerConfig::default()
+    });
+    let mut canonical: HttpRequest =
+      "GET / HTTP/1.1\r\nHost: WWW.example.com\r\nX-Forwarded-Proto: https\r\n\r\n".into();
+    canonical.trust_proxy = true;
+    let mut plain: HttpRequest = "GET / HTTP/1.1\r\nHost: www.example.com\r\n\r\n".into();
+    plain.trust_proxy = true;
+
+    assert_eq!(router.route(&canonical).status_code(), "200");
+    assert_eq!(router.route(&canonical).header("Location"), None);
+    assert_eq!(router.route(&plain).header("Location"), Some("https://www.example.com/"));
+  }
+
+  #[test]
+  fn test_unsupported_version_rejected() {
+    let router = Router::new(ServerConfig::default());
+    let response = route_to_string(&router, "GET / HTTP/9.9\r\nHost: localhost\r\n\r\n");
+    let garbage = route_to_string(&router, "hello there\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 505 HTTP Version Not Supported\r\n"));
+    assert!(garbage.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+  }
+
+  #[test]
+  fn test_request_without_version_allowed() {
+    let router = Router::new(ServerConfig {
+      allow_http_0_9: true,
+      ..ServerConfig::default()
+    });
+    let request: HttpRequest = "GET /health\r\n\r\n".into();
+
+    assert_eq!(router.route(&request).status_code(), "200");
+  }
+
+  /// Router answering the given path with a body of the given type and length.
+  fn router_serving(path: &str, content_type: &'static str, length: usize) -> Router {
+    let mut router = Router::new(ServerConfig::default());
+    router.add_route(Method::GET, path, move |_request| {
+      let mut response = HttpResponse::new("200", None, Some("a".repeat(length)));
+      response.set_header("Content-Type", content_type);
+      response
+    });
+    router
+  }
+
+  /// Request for the given path from a client accepting gzip.
+  fn gzip_request(path: &str) -> HttpRequest {
+    format!("GET {} HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\n\r\n", path).into()
+  }
+
+  #[test]
+  fn test_small_json_not_compressed() {
+    let router = router_serving("/api/small", "application/json", 100);
+    let request = gzip_request("/api/small");
+    let response = router.route(&request);
+
+    assert_eq!(response.header("Content-Encoding"), None);
+    assert_eq!(response.body_bytes().len(), 100);
+  }
+
+  #[test]
+  fn test_large_html_compressed() {
+    let router = router_serving("/large", "text/html; charset=utf-8", 10_000);
+    let request = gzip_request("/large");
+    let response = router.route(&request);
+
+    assert_eq!(response.header("Content-Encoding"), Some("gzip"));
+    assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+    assert_eq!(compress::tests::gunzip(response.body_bytes()), "a".repeat(10_000).into_bytes());
+
+    let plain: HttpRequest = "GET /large HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    assert_eq!(router.route(&plain).header("Content-Encoding"), None);
+  }
+
+  #[test]
+  fn test_preferred_compression_chosen() {
+    let router = router_serving("/large", "text/plain", 10_000);
+
+    let request: HttpRequest =
+      "GET /large HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip;q=0.5, deflate\r\n\r\n".into();
+    let response = router.route(&request);
+    assert_eq!(response.header("Content-Encoding"), Some("deflate"));
+    assert_eq!(compress::tests::unzlib(response.body_bytes()), "a".repeat(10_000).into_bytes());
+
+    let request: HttpRequest =
+      "GET /large HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: deflate;q=0.5, gzip\r\n\r\n".into();
+    let response = router.route(&request);
+    assert_eq!(response.header("Content-Encoding"), Some("gzip"));
+    assert_eq!(compress::tests::gunzip(response.body_bytes()), "a".repeat(10_000).into_bytes());
+  }
+
+  #[test]
+  fn test_compressed_body_not_advertising_ranges() {
+    let mut router = Router::new(ServerConfig::default());
+    router.add_route(Method::GET, "/notes.txt", |_request| {
+      let mut response = HttpResponse::new("200", None, Some("a".repeat(10_000)));
+      response.set_header("Content-Type", "text/plain");
+      response.set_header("Accept-Ranges", "bytes");
+      response
+    });
+
+    let compressed = gzip_request("/notes.txt");
+    let plain: HttpRequest = "GET /notes.txt HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    let compressed = router.route(&compressed);
+    let plain = router.route(&plain);
+
+    assert_eq!(compressed.header("Content-Encoding"), Some("gzip"));
+    assert_eq!(compressed.header("Accept-Ranges"), Some("none"));
+    assert_eq!(plain.header("Accept-Ranges"), Some("bytes"));
+  }
+
+  #[test]
+  fn test_png_never_compressed() {
+    let router = router_serving("/logo.png", "image/png", 10_000);
+    let request = gzip_request("/logo.png");
+    let response = router.route(&request);
+
+    assert_eq!(response.header("Content-Encoding"), None);
+  }
+
+  /// Router serving a temporary directory, holding `app.js` and `js/lib.js`, under `/static`.
+  fn mounted_router(name: &str) -> Router {
+    let dir = std::env::temp_dir().join(format!("httpserver-mount-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("js")).unwrap();
+    fs::write(dir.join("app.js"), "console.log('app');").unwrap();
+    fs::write(dir.join("js/lib.js"), "console.log('lib');").unwrap();
+
+    let mut router = Router::new(ServerConfig::default());
+    router.mount_static("/static/", dir.to_str().unwrap());
+    router
+  }
+
+  #[test]
+  fn test_mounted_file_served() {
+    let router = mounted_router("served");
+
+    let request: HttpRequest = "GET /static/app.js HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    let response = router.route(&request);
+    assert_eq!(response.status_code(), "200");
+    assert_eq!(response.header("Content-Type"), Some("text/javascript; charset=utf-8"));
+    assert_eq!(response.body(), "console.log('app');");
+
+    let request: HttpRequest = "GET /static/js/lib.js HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    assert_eq!(router.route(&request).body(), "console.log('lib');");
+
+    // The public directory does not have the file
+    let request: HttpRequest = "GET /app.js HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    assert_eq!(router.route(&request).status_code(), "404");
+    let request: HttpRequest = "GET /staticapp.js HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    assert_eq!(router.route(&request).status_code(), "404");
+  }
+
+  #[test]
+  fn test_mounted_directory_traversal_refused() {
+    let router = mounted_router("traversal");
+
+    for path in ["/static/../Cargo.toml", "/static/js/../../Cargo.toml", "/static/.env"] {
+      let request: HttpRequest = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).into();
+      assert_eq!(router.route(&request).status_code(), "403", "{}", path);
+    }
+    let request: HttpRequest = "GET /static/missing.js HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    assert_eq!(router.route(&request).status_code(), "404");
+    let request: HttpRequest = "POST /static/app.js HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    assert_eq!(router.route(&request).status_code(), "405");
+  }
+
+  #[test]
+  fn test_default_favicon_served() {
+    let router = Router::new(ServerConfig::default());
+    let request: HttpRequest = "GET /favicon.ico HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    let response = router.route(&request);
+
+    assert_eq!(response.status_code(), "200");
+    assert_eq!(response.header("Content-Type"), Some("image/x-icon"));
+    assert_eq!(response.header("Cache-Control"), Some("public, max-age=604800"));
+    assert_eq!(response.body_bytes(), DEFAULT_FAVICON);
+  }
+
+  #[test]
+  fn test_configured_favicon_served() {
+    let path = std::env::temp_dir().join(format!("favicon-{}.ico", std::process::id()));
+    fs::write(&path, b"\x00\x00\x01\x00icon").unwrap();
+    let router = Router::new(ServerConfig {
+      favicon: Some(path.to_string_lossy().into_owned()),
+      ..ServerConfig::default()
+    });
+    fs::remove_file(&path).unwrap();
+
+    // The icon was read once, so it is still served after the file is gone
+    let request: HttpRequest = "GET /favicon.ico HTTP/1.1\r\nHost: localhost\r\n\r\n".into();
+    assert_eq!(router.route(&request).body_bytes(), b"\x00\x00\x01\x00icon");
+  }
+
+  #[test]
+  fn test_unknown_method_bad_request() {
+    let router = Router::new(ServerConfig::default());
+    let response = route_to_string(&router, "BREW /x HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+  }
+}
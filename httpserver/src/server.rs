@@ -1,13 +1,56 @@
-use std::{net::{TcpListener, TcpStream}, io::Read};
+use std::{
+  io,
+  net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  },
+  time::Duration,
+};
 
-use http::httprequest::HttpRequest;
+use http::{
+  httprequest::{HttpRequest, Method},
+  httpresponse::HttpResponse,
+};
+use log::{debug, error, info, warn};
 
-use crate::router::Router;
+use crate::{config::ServerConfig, connection, cors::CorsPolicy, pool::ThreadPool, router::Router};
+
+/// Maximum time spent sending the response of a rejected connection.
+const REJECT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Represents a connection counted as open until it is dropped.
+struct OpenConnection {
+  /// Number of connections open, shared by the accept loop and the workers.
+  count: Arc<AtomicUsize>,
+}
+
+impl OpenConnection {
+  /// Counts a new open connection.
+  ///
+  /// # Arguments
+  ///
+  /// * `count`: Number of connections open.
+  fn new(count: &Arc<AtomicUsize>) -> Self {
+    count.fetch_add(1, Ordering::SeqCst);
+    Self { count: Arc::clone(count) }
+  }
+}
+
+impl Drop for OpenConnection {
+  fn drop(&mut self) {
+    self.count.fetch_sub(1, Ordering::SeqCst);
+  }
+}
 
 /// Represents a server.
 pub struct Server<'a> {
   /// Socket address to listen connections.
   socket_address: &'a str,
+  /// Listener already bound for the server, used instead of the socket address.
+  listener: Option<TcpListener>,
+  /// Router used to dispatch the incoming requests.
+  router: Arc<Router>,
 }
 
 impl<'a> Server<'a> {
@@ -17,30 +60,428 @@ impl<'a> Server<'a> {
   /// 
   /// * `socket_address`: Socket address to listen new connections.
   pub fn new(socket_address: &'a str) -> Self {
+    Self::with_config(socket_address, ServerConfig::default())
+  }
+
+  /// Creates a new [`Server`] object with the given configuration.
+  ///
+  /// # Arguments
+  ///
+  /// * `socket_address`: Socket address to listen new connections.
+  /// * `config`: Configuration of the server.
+  pub fn with_config(socket_address: &'a str, config: ServerConfig) -> Self {
     Self {
-      socket_address: socket_address,
+      socket_address,
+      listener: None,
+      router: Arc::new(Router::new(config)),
+    }
+  }
+
+  /// Creates a new [`Server`] object accepting the connections of an already bound
+  /// listener, e.g. one bound to port 0 or handed over by the system at startup.
+  ///
+  /// # Arguments
+  ///
+  /// * `listener`: Listener to accept new connections from.
+  pub fn from_listener(listener: TcpListener) -> Server<'static> {
+    Self::from_listener_with_config(listener, ServerConfig::default())
+  }
+
+  /// Creates a new [`Server`] object accepting the connections of an already bound
+  /// listener, with the given configuration.
+  ///
+  /// # Arguments
+  ///
+  /// * `listener`: Listener to accept new connections from.
+  /// * `config`: Configuration of the server.
+  pub fn from_listener_with_config(listener: TcpListener, config: ServerConfig) -> Server<'static> {
+    Server {
+      socket_address: "",
+      listener: Some(listener),
+      router: Arc::new(Router::new(config)),
     }
   }
 
+  /// Gets the local address of the listener of the server, once bound.
+  pub fn local_addr(&self) -> Option<SocketAddr> {
+    self.listener.as_ref().and_then(|listener| listener.local_addr().ok())
+  }
+
+  /// Registers a closure as the handler of the given method and path.
+  ///
+  /// Routes must be registered before the server runs.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method the route answers to.
+  /// * `path`: Exact path the route answers to (e.g. `/api/ping`).
+  /// * `handler`: Function producing the response of the route.
+  pub fn add_route<F>(&mut self, method: Method, path: &str, handler: F)
+  where
+    F: Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static,
+  {
+    Arc::get_mut(&mut self.router)
+      .expect("routes must be registered before the server runs")
+      .add_route(method, path, handler);
+  }
+
+  /// Registers a closure as the handler of the given method and path, accepting request
+  /// bodies up to the given length instead of the configured one.
+  ///
+  /// Routes must be registered before the server runs.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method the route answers to.
+  /// * `path`: Exact path the route answers to (e.g. `/upload`).
+  /// * `max_body_size`: Maximum length of the request bodies, in bytes.
+  /// * `handler`: Function producing the response of the route.
+  pub fn add_route_with_max_body<F>(&mut self, method: Method, path: &str, max_body_size: usize, handler: F)
+  where
+    F: Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static,
+  {
+    Arc::get_mut(&mut self.router)
+      .expect("routes must be registered before the server runs")
+      .add_route_with_max_body(method, path, max_body_size, handler);
+  }
+
+  /// Registers a closure as the `PATCH` handler of the given path, accepting patches of the
+  /// given media type, announced with `Accept-Patch`.
+  ///
+  /// Routes must be registered before the server runs.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Exact path the route answers to (e.g. `/api/profile`).
+  /// * `accept_patch`: Media type of the accepted patches (e.g. `application/merge-patch+json`).
+  /// * `handler`: Function producing the response of the route.
+  pub fn add_patch_route<F>(&mut self, path: &str, accept_patch: &str, handler: F)
+  where
+    F: Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static,
+  {
+    Arc::get_mut(&mut self.router)
+      .expect("routes must be registered before the server runs")
+      .add_patch_route(path, accept_patch, handler);
+  }
+
+  /// Serves the files of the given directory under the given path prefix.
+  ///
+  /// Directories must be mounted before the server runs.
+  ///
+  /// # Arguments
+  ///
+  /// * `prefix`: Path prefix the directory is served under (e.g. `/static`).
+  /// * `directory`: Directory the files are served from.
+  pub fn mount_static(&mut self, prefix: &str, directory: &str) {
+    Arc::get_mut(&mut self.router)
+      .expect("directories must be mounted before the server runs")
+      .mount_static(prefix, directory);
+  }
+
+  /// Sets the CORS policy of the given path.
+  ///
+  /// Policies must be set before the server runs.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Exact path the policy applies to (e.g. `/api/ping`).
+  /// * `policy`: CORS policy of the path.
+  pub fn set_cors(&mut self, path: &str, policy: CorsPolicy) {
+    Arc::get_mut(&mut self.router)
+      .expect("CORS policies must be set before the server runs")
+      .set_cors(path, policy);
+  }
+
+  /// Gets a handle on the router of the server, used to turn the maintenance mode
+  /// on or off while the server runs.
+  pub fn router(&self) -> Arc<Router> {
+    Arc::clone(&self.router)
+  }
+
+  /// Binds the server to its socket address, unless it is bound already, and returns
+  /// the local address it listens to, e.g. to learn the port picked for port 0.
+  pub fn bind(&mut self) -> io::Result<SocketAddr> {
+    let connection_listener = match self.listener.take() {
+      Some(listener) => listener,
+      None => TcpListener::bind(self.socket_address)?,
+    };
+    self.listener.insert(connection_listener).local_addr()
+  }
+
+  /// Serves the connections of the bound server, until the listener fails.
+  ///
+  /// Returns a `NotConnected` error if the server was not bound, and a `NotFound` error
+  /// if a directory the files are served from does not exist.
+  pub fn serve(&self) -> io::Result<()> {
+    let connection_listener = self.listener.as_ref().ok_or_else(|| {
+      io::Error::new(io::ErrorKind::NotConnected, "the server must be bound before serving")
+    })?;
+    self.router.check_directories()?;
+
+    info!("Server running on {}", connection_listener.local_addr()?);
+
+    accept(connection_listener, &self.router);
+    Ok(())
+  }
+
   /// Runs the server
-  pub fn run(&self) {
+  ///
+  /// # Panics
+  ///
+  /// Panics if the socket address cannot be bound.
+  pub fn run(&mut self) {
     // Start the server on the socket address
-    let connection_listener : TcpListener= TcpListener::bind(self.socket_address).unwrap();
+    self.bind().expect("failed to bind the socket address of the server");
+    if let Err(e) = self.serve() {
+      error!("The server stopped: {}", e);
+    }
+  }
+}
 
-    println!("Server running on {}", self.socket_address);
+/// Accepts the connections of the given listener, serving them on a pool of threads.
+///
+/// A failing connection is dropped without affecting the loop or the other connections.
+/// Once every thread is busy and the queue of waiting connections is full, new ones are
+/// answered with `503 Service Unavailable` and closed, as are the ones beyond the
+/// configured maximum number of open connections.
+///
+/// # Arguments
+///
+/// * `listener`: Listener to accept connections from.
+/// * `router`: Router used to dispatch the requests.
+fn accept(listener: &TcpListener, router: &Arc<Router>) {
+  let config = router.config();
+  let pool = ThreadPool::new(config.worker_threads, config.queue_capacity);
+  let open = Arc::new(AtomicUsize::new(0));
 
-    // Listen and waits for new connections
-    for stream in connection_listener.incoming() {
-      let mut stream : TcpStream = stream.unwrap();
-      println!("Connection established with client.");
-      // Create the request from the byte stream received
-      let mut read_buffer = [0; 90];
-      stream.read(&mut read_buffer).unwrap();
+  // Listen and waits for new connections
+  for stream in listener.incoming() {
+    let stream : TcpStream = match stream {
+      Ok(stream) => stream,
+      Err(e) => {
+        warn!("Failed to accept a connection: {}", e);
+        continue;
+      }
+    };
+    match stream.peer_addr() {
+      Ok(peer) => info!("Connection established with client {}.", peer),
+      Err(_) => info!("Connection established with client."),
+    }
 
-      let req: HttpRequest = String::from_utf8(read_buffer.to_vec()).unwrap().into();
+    // Decline the connection rather than queueing it without bound. This loop is the
+    // only one queueing jobs, so the pool cannot fill up between the check and the job.
+    if pool.is_saturated() {
+      warn!("Rejecting a connection: every worker is busy and the queue is full");
+      reject_busy(stream, config);
+      continue;
+    }
+    // Connections are only closed by the workers, so the count cannot grow past the check
+    if config.max_connections.is_some_and(|max| open.load(Ordering::SeqCst) >= max) {
+      warn!("Rejecting a connection: the maximum number of open connections is reached");
+      reject_busy(stream, config);
+      continue;
+    }
 
-      // Route the request to the appropiate handler
-      Router::route(req, &mut stream);
+    // Serve the requests of the connection until it is closed
+    let router = Arc::clone(router);
+    let counted = OpenConnection::new(&open);
+    let job = move || {
+      connection::serve_isolated(stream, &router);
+      drop(counted);
+    };
+    if pool.try_execute(job).is_err() {
+      warn!("Dropping a connection the pool rejected");
     }
   }
-}
\ No newline at end of file
+}
+
+/// Answers a connection the server is too busy to serve with `503` and closes it.
+///
+/// # Arguments
+///
+/// * `stream`: TCP stream of the client connection.
+/// * `config`: Configuration of the server.
+fn reject_busy(mut stream: TcpStream, config: &ServerConfig) {
+  let mut response = HttpResponse::new("503", None, None);
+  response.set_retry_after(config.busy_retry_after);
+  response.set_header("Connection", "close");
+  config.apply_default_headers(&mut response);
+
+  // Do not let a slow client stall the accept loop
+  let _ = stream.set_write_timeout(Some(REJECT_TIMEOUT));
+  if let Err(e) = response.send_response(&mut stream) {
+    debug!("Failed to reject a connection: {}", e);
+  }
+  let _ = stream.shutdown(Shutdown::Both);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{
+    io::{BufReader, Read, Write},
+    thread,
+  };
+
+  /// Starts accepting connections in the background and returns the address to connect to.
+  fn start(config: ServerConfig) -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let router = Arc::new(Router::new(config));
+    thread::spawn(move || accept(&listener, &router));
+    address
+  }
+
+  /// Sends a request over the given connection and reads the response up to its body.
+  fn exchange(client: &mut TcpStream, raw: &str) -> String {
+    client.write_all(raw.as_bytes()).unwrap();
+    let mut reader = BufReader::new(client.try_clone().unwrap());
+    let mut buffer: Vec<u8> = Vec::new();
+    let response = connection::read_request(&mut reader, &mut buffer).unwrap().unwrap();
+    String::from_utf8_lossy(response).into_owned()
+  }
+
+  #[test]
+  fn test_server_from_listener() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = Server::from_listener(listener);
+    let address = server.local_addr().unwrap();
+    assert_ne!(address.port(), 0);
+    assert_eq!(Server::new("127.0.0.1:0").local_addr(), None);
+
+    thread::spawn(move || server.serve());
+    let mut client = TcpStream::connect(address).unwrap();
+    let response = exchange(&mut client, "GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+  }
+
+  #[test]
+  fn test_server_from_listener_with_config() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let config = ServerConfig {
+      default_headers: vec![("X-Frame-Options".to_string(), "DENY".to_string())],
+      ..ServerConfig::default()
+    };
+    let server = Server::from_listener_with_config(listener, config);
+    let address = server.local_addr().unwrap();
+
+    thread::spawn(move || server.serve());
+    let mut client = TcpStream::connect(address).unwrap();
+    let response = exchange(&mut client, "GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("X-Frame-Options:DENY\r\n"));
+  }
+
+  #[test]
+  fn test_serve_rejects_missing_directory() {
+    let mut server = Server::new("127.0.0.1:0");
+    server.mount_static("/assets", "/nonexistent/assets");
+    server.bind().unwrap();
+
+    let error = server.serve().unwrap_err();
+
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    assert!(error.to_string().contains("/nonexistent/assets"));
+  }
+
+  #[test]
+  fn test_bind_returns_local_address() {
+    let mut server = Server::new("127.0.0.1:0");
+    let error = server.serve().unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotConnected);
+
+    let address = server.bind().unwrap();
+    assert_ne!(address.port(), 0);
+    assert_eq!(server.local_addr(), Some(address));
+    assert_eq!(server.bind().unwrap(), address);
+
+    thread::spawn(move || server.serve());
+    let mut client = TcpStream::connect(address).unwrap();
+    let response = exchange(&mut client, "GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+  }
+
+  #[test]
+  fn test_overflow_connection_rejected() {
+    let config = ServerConfig {
+      worker_threads: 1,
+      queue_capacity: 0,
+      ..ServerConfig::default()
+    };
+    let address = start(config);
+
+    // Keep the only worker busy with a keep-alive connection
+    let mut busy = TcpStream::connect(address).unwrap();
+    let first = exchange(&mut busy, "GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert!(first.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    let mut overflow = TcpStream::connect(address).unwrap();
+    let mut response = String::new();
+    overflow.read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    assert!(response.contains("Retry-After:1\r\n"));
+    assert!(response.contains("Connection:close\r\n"));
+  }
+
+  #[test]
+  fn test_connection_rejected_beyond_maximum() {
+    let config = ServerConfig {
+      max_connections: Some(1),
+      ..ServerConfig::default()
+    };
+    let address = start(config);
+
+    let mut open = TcpStream::connect(address).unwrap();
+    let first = exchange(&mut open, "GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert!(first.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    // Workers are idle, yet the second connection is one too many
+    let mut second = TcpStream::connect(address).unwrap();
+    let mut response = String::new();
+    second.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+
+    // Closing the first connection makes room for a new one
+    open.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    open.read_to_end(&mut Vec::new()).unwrap();
+    drop(open);
+    let served = (0..50).any(|_| {
+      thread::sleep(Duration::from_millis(10));
+      let mut third = TcpStream::connect(address).unwrap();
+      exchange(&mut third, "GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n").starts_with("HTTP/1.1 200 OK\r\n")
+    });
+    assert!(served);
+  }
+
+  #[test]
+  fn test_failing_connections_isolated() {
+    let address = start(ServerConfig::default());
+    let mut healthy = TcpStream::connect(address).unwrap();
+
+    // Invalid UTF-8 is rejected
+    let mut invalid = TcpStream::connect(address).unwrap();
+    invalid.write_all(b"GET /\xff\xfe HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    // A truncated request line is rejected
+    let mut truncated = TcpStream::connect(address).unwrap();
+    truncated.write_all(b"GET HTTP/1.1\r\n\r\n").unwrap();
+    // An abrupt disconnect fails in the middle of the headers
+    let mut dropped = TcpStream::connect(address).unwrap();
+    dropped.write_all(b"GET /health HTTP/1.1\r\nHost: loc").unwrap();
+    drop(dropped);
+
+    // The failing connections are closed
+    let mut rest = Vec::new();
+    let _ = invalid.read_to_end(&mut rest);
+    let _ = truncated.read_to_end(&mut rest);
+
+    healthy.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    BufReader::new(healthy).read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+  }
+}
@@ -0,0 +1,170 @@
+use std::{
+  collections::{hash_map::RandomState, HashMap},
+  hash::{BuildHasher, Hasher},
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use http::{httprequest::HttpRequest, httpresponse::HttpResponse};
+
+/// Name of the cookie carrying the session identifier.
+pub const SESSION_COOKIE: &str = "session_id";
+
+/// Values stored in a session, by name.
+pub type SessionData = HashMap<String, String>;
+
+/// Represents a session kept in the store.
+struct Session {
+  /// Values stored by the handlers.
+  data: SessionData,
+  /// Time after which the session is forgotten, pushed back by every request.
+  expires: Instant,
+}
+
+/// Represents an in-memory store of the sessions of the clients, identified by a cookie.
+///
+/// Clones share the same sessions, so a single store can back several routes. Sessions
+/// are lost when the server stops.
+#[derive(Clone)]
+pub struct SessionStore {
+  /// Sessions by identifier.
+  sessions: Arc<Mutex<HashMap<String, Session>>>,
+  /// Time a session is kept for after its last request.
+  ttl: Duration,
+}
+
+impl SessionStore {
+  /// Creates a new [`SessionStore`] object without any session.
+  ///
+  /// # Arguments
+  ///
+  /// * `ttl`: Time a session is kept for after its last request.
+  pub fn new(ttl: Duration) -> Self {
+    Self {
+      sessions: Arc::new(Mutex::new(HashMap::new())),
+      ttl,
+    }
+  }
+
+  /// Wraps a handler with access to the session of the client, so it can be registered
+  /// as a route.
+  ///
+  /// A client without a valid session is issued a new one with a `Set-Cookie` header.
+  /// The values the handler leaves in the session are kept for the next requests.
+  ///
+  /// # Arguments
+  ///
+  /// * `handler`: Function producing the response from the request and its session.
+  pub fn handler<F>(&self, handler: F) -> impl Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static
+  where
+    F: Fn(&HttpRequest, &mut SessionData) -> HttpResponse<'static> + Send + Sync + 'static,
+  {
+    let store = self.clone();
+    move |request| {
+      let (id, mut data) = store.load(request);
+      let mut response = handler(request, &mut data);
+      if request.cookie(SESSION_COOKIE) != Some(id.as_str()) {
+        response.add_header("Set-Cookie", &format!("{}={}; Path=/; HttpOnly; SameSite=Lax", SESSION_COOKIE, id));
+      }
+      store.save(id, data);
+      response
+    }
+  }
+
+  /// Gets the identifier and the values of the session of the given request, starting a
+  /// new session if it has none or it expired.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request of the client.
+  pub fn load(&self, request: &HttpRequest) -> (String, SessionData) {
+    let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+    let now = Instant::now();
+    sessions.retain(|_, session| session.expires > now);
+
+    match request.cookie(SESSION_COOKIE).and_then(|id| Some((id, sessions.get(id)?))) {
+      Some((id, session)) => (id.to_string(), session.data.clone()),
+      None => (Self::new_id(), SessionData::new()),
+    }
+  }
+
+  /// Stores the values of the given session, keeping it for another time to live.
+  ///
+  /// # Arguments
+  ///
+  /// * `id`: Identifier of the session.
+  /// * `data`: Values of the session.
+  pub fn save(&self, id: String, data: SessionData) {
+    let expires = Instant::now() + self.ttl;
+    let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+    sessions.insert(id, Session { data, expires });
+  }
+
+  /// Generates a new identifier of 128 bits, for sessions and their tokens, hard to guess
+  /// as it comes from randomly seeded hashers.
+  pub(crate) fn new_id() -> String {
+    let half = || RandomState::new().build_hasher().finish();
+    format!("{:016x}{:016x}", half(), half())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Wraps a handler counting the requests of each session.
+  fn counter(store: &SessionStore) -> impl Fn(&HttpRequest) -> HttpResponse<'static> {
+    store.handler(|_request, session| {
+      let count: u32 = session.get("count").and_then(|c| c.parse().ok()).unwrap_or(0) + 1;
+      session.insert("count".to_string(), count.to_string());
+      HttpResponse::new("200", None, Some(count.to_string()))
+    })
+  }
+
+  /// Gets the session identifier issued by the given response.
+  fn issued_id(response: &HttpResponse) -> String {
+    let cookie = response.header("Set-Cookie").unwrap();
+    let (pair, _) = cookie.split_once(';').unwrap();
+    pair.strip_prefix("session_id=").unwrap().to_string()
+  }
+
+  #[test]
+  fn test_session_persists_across_requests() {
+    let store = SessionStore::new(Duration::from_secs(60));
+    let handler = counter(&store);
+
+    let first = handler(&"GET /count HTTP/1.1\r\nHost: localhost\r\n\r\n".into());
+    let id = issued_id(&first);
+    let request: HttpRequest = format!("GET /count HTTP/1.1\r\nHost: localhost\r\nCookie: session_id={}\r\n\r\n", id).into();
+    let second = handler(&request);
+
+    assert_eq!(first.body(), "1");
+    assert_eq!(id.len(), 32);
+    assert_eq!(second.body(), "2");
+    assert_eq!(second.header("Set-Cookie"), None);
+  }
+
+  #[test]
+  fn test_unknown_session_replaced() {
+    let store = SessionStore::new(Duration::from_secs(60));
+    let handler = counter(&store);
+
+    let response = handler(&"GET /count HTTP/1.1\r\nHost: localhost\r\nCookie: session_id=forged\r\n\r\n".into());
+
+    assert_eq!(response.body(), "1");
+    assert_ne!(issued_id(&response), "forged");
+  }
+
+  #[test]
+  fn test_expired_session_forgotten() {
+    let store = SessionStore::new(Duration::ZERO);
+    let handler = counter(&store);
+
+    let id = issued_id(&handler(&"GET /count HTTP/1.1\r\nHost: localhost\r\n\r\n".into()));
+    let request: HttpRequest = format!("GET /count HTTP/1.1\r\nHost: localhost\r\nCookie: session_id={}\r\n\r\n", id).into();
+    let response = handler(&request);
+
+    assert_eq!(response.body(), "1");
+    assert_ne!(issued_id(&response), id);
+  }
+}
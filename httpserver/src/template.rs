@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+/// Represents what becomes of a placeholder with no matching variable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unmatched {
+  /// The placeholder is left in the output as-is.
+  Keep,
+  /// The placeholder is removed from the output.
+  Blank,
+}
+
+/// Escapes the characters with a special meaning in HTML, so that text can be embedded
+/// safely in an HTML body or attribute.
+///
+/// # Arguments
+///
+/// * `text`: Text to escape.
+pub fn html_escape(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      _ => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+/// Substitutes the `{{name}}` placeholders of a template with the HTML-escaped value of
+/// the respective variable.
+///
+/// # Arguments
+///
+/// * `template`: Contents of the template.
+/// * `vars`: Values of the variables, by name.
+/// * `unmatched`: What becomes of the placeholders with no matching variable.
+pub fn render(template: &str, vars: &HashMap<&str, &str>, unmatched: Unmatched) -> String {
+  let mut output = String::with_capacity(template.len());
+  let mut rest = template;
+
+  while let Some(start) = rest.find("{{") {
+    let Some(length) = rest[start + 2..].find("}}") else {
+      break;
+    };
+    let placeholder = &rest[start..start + 2 + length + 2];
+    let name = placeholder[2..placeholder.len() - 2].trim();
+
+    output.push_str(&rest[..start]);
+    match (vars.get(name), unmatched) {
+      (Some(value), _) => output.push_str(&html_escape(value)),
+      (None, Unmatched::Keep) => output.push_str(placeholder),
+      (None, Unmatched::Blank) => {}
+    }
+    rest = &rest[start + placeholder.len()..];
+  }
+
+  output.push_str(rest);
+  output
+} // end fn render()
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_render_two_variables() {
+    let vars = HashMap::from([("name", "Ada"), ("count", "3")]);
+    let rendered = render("<p>Hello {{name}}, you have {{ count }} orders</p>", &vars, Unmatched::Keep);
+
+    assert_eq!(rendered, "<p>Hello Ada, you have 3 orders</p>");
+  }
+
+  #[test]
+  fn test_render_escapes_values() {
+    let vars = HashMap::from([("name", "<script>alert(1)</script>"), ("company", "Smith & Co")]);
+    let rendered = render("<p>{{name}} from {{company}}</p>", &vars, Unmatched::Keep);
+
+    assert_eq!(rendered, "<p>&lt;script&gt;alert(1)&lt;/script&gt; from Smith &amp; Co</p>");
+  }
+
+  #[test]
+  fn test_render_unmatched_placeholders() {
+    let vars = HashMap::from([("name", "Ada")]);
+
+    assert_eq!(render("{{name}} {{missing}}", &vars, Unmatched::Keep), "Ada {{missing}}");
+    assert_eq!(render("{{name}} {{missing}}", &vars, Unmatched::Blank), "Ada ");
+    assert_eq!(render("{{name}} {{unclosed", &vars, Unmatched::Blank), "Ada {{unclosed");
+  }
+}
@@ -7,7 +7,7 @@ for stream in connection_listener.incoming() {
 let mut stream = stream.unwrap();
 println!("Connection established");
 let mut buffer = [0; 1024];
-stream.read(&mut buffer).unwrap();
-stream.write(&mut buffer).unwrap();
+let bytes_read = stream.read(&mut buffer).unwrap();
+stream.write_all(&buffer[..bytes_read]).unwrap();
 }
 }
\ No newline at end of file